@@ -0,0 +1,195 @@
+use crate::constants::TRASH_ID;
+use crate::error::Error;
+use crate::filesystem::sanitize_file_name;
+use crate::objects::{DocumentType, FileTree, Node};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+pub const ARCHIVE_DIR_NAME: &str = ".archive";
+
+/// Record of what a backup directory contains and which docSchema hash every
+/// local file was written from.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub root_hash: String,
+    pub generation: u64,
+    pub updated: Option<DateTime<Utc>>,
+    /// Entries keyed by document ID. A `BTreeMap` keeps the file stable between runs.
+    pub entries: BTreeMap<String, ManifestEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Hash of the docSchema the local copy was created from
+    pub hash: String,
+    pub name: String,
+    /// Parent ID as stored in the metadata ("" for the root)
+    pub parent: String,
+    pub doc_type: DocumentType,
+    pub pinned: bool,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Location relative to the backup directory
+    pub path: PathBuf,
+}
+
+/// What to do with local copies of documents that were deleted remotely.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RemovedPolicy {
+    /// Move them into a timestamped folder below `.archive`
+    #[default]
+    Archive,
+    /// Delete them
+    Delete,
+}
+
+#[derive(Debug, Default)]
+pub struct BackupReport {
+    pub downloaded: Vec<PathBuf>,
+    pub moved: Vec<(PathBuf, PathBuf)>,
+    pub unchanged: usize,
+    pub removed: Vec<PathBuf>,
+    pub failed: Vec<(PathBuf, Error)>,
+}
+
+#[derive(Debug, Default)]
+pub struct RestoreReport {
+    pub restored: Vec<PathBuf>,
+    /// Entries whose docSchema hash or ID is already present remotely
+    pub skipped: usize,
+    pub failed: Vec<(PathBuf, Error)>,
+}
+
+impl BackupManifest {
+    /// Loads the manifest from `dir`, returning an empty one for a fresh backup.
+    pub fn load(dir: &Path) -> Result<Self, Error> {
+        let path = dir.join(MANIFEST_FILE_NAME);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let data = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), Error> {
+        // Write next to the real manifest first so an interrupted run never
+        // leaves a truncated file behind.
+        let path = dir.join(MANIFEST_FILE_NAME);
+        let tmp_path = dir.join(format!("{}.tmp", MANIFEST_FILE_NAME));
+        fs::write(&tmp_path, serde_json::to_string_pretty(self)?)?;
+        fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+}
+
+/// Computes where every node of `tree` lives inside a backup directory.
+///
+/// Documents map to a path without extension, folders to their directory.
+/// Names are sanitised and siblings whose names collide get a short ID
+/// suffix, so the layout is the same on every run. The trash is skipped.
+pub fn plan_paths(tree: &FileTree) -> Vec<(PathBuf, &Node)> {
+    let mut planned = Vec::new();
+    plan_children(&tree.root, Path::new(""), &mut planned);
+    planned
+}
+
+fn plan_children<'a>(node: &'a Node, prefix: &Path, planned: &mut Vec<(PathBuf, &'a Node)>) {
+    let mut children: Vec<&Node> = node
+        .children
+        .values()
+        .filter(|child| child.id() != TRASH_ID)
+        .collect();
+    children.sort_by_key(|child| child.id());
+
+    let names: Vec<String> = children
+        .iter()
+        .map(|child| sanitize_file_name(child.name()))
+        .collect();
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for name in &names {
+        *counts.entry(name.to_lowercase()).or_default() += 1;
+    }
+
+    for (child, name) in children.into_iter().zip(names) {
+        let lower = name.to_lowercase();
+        let reserved = prefix.as_os_str().is_empty()
+            && (lower == MANIFEST_FILE_NAME || lower == ARCHIVE_DIR_NAME);
+        let name = if counts[&lower] > 1 || reserved {
+            format!("{} ({})", name, child.short_id())
+        } else {
+            name
+        };
+
+        let path = prefix.join(name);
+        if child.is_directory() {
+            plan_children(child, &path, planned);
+        }
+        planned.push((path, child));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::Document;
+    use uuid::Uuid;
+
+    fn doc(id: &str, name: &str, parent: &str, doc_type: DocumentType) -> Document {
+        Document {
+            id: Uuid::parse_str(id).unwrap(),
+            display_name: name.to_string(),
+            parent: parent.to_string(),
+            doc_type,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_plan_paths() {
+        let folder = "11111111-0000-0000-0000-000000000000";
+        let tree = FileTree::build(vec![
+            doc(folder, "Papers", "", DocumentType::Collection),
+            doc(
+                "22222222-0000-0000-0000-000000000000",
+                "Notes",
+                folder,
+                DocumentType::Document,
+            ),
+            doc(
+                "33333333-0000-0000-0000-000000000000",
+                "notes",
+                folder,
+                DocumentType::Document,
+            ),
+            doc(
+                "44444444-0000-0000-0000-000000000000",
+                "a/b",
+                "",
+                DocumentType::Document,
+            ),
+            doc(
+                "55555555-0000-0000-0000-000000000000",
+                "Deleted",
+                "trash",
+                DocumentType::Document,
+            ),
+        ]);
+
+        let mut paths: Vec<PathBuf> = plan_paths(&tree).into_iter().map(|(p, _)| p).collect();
+        paths.sort();
+
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("Papers"),
+                PathBuf::from("Papers/Notes (22222222)"),
+                PathBuf::from("Papers/notes (33333333)"),
+                PathBuf::from("a_b"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use crate::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// Size the default cache is pruned to after an operation
+pub const DEFAULT_MAX_CACHE_SIZE: u64 = 512 * 1024 * 1024;
+
+/// Local store for blobs downloaded from the cloud.
+///
+/// Blobs are addressed by their hash and never change, so entries never need
+/// invalidation. The least recently used blobs are evicted once the cache
+/// grows beyond its size limit.
+#[derive(Debug, Clone)]
+pub struct BlobCache {
+    dir: PathBuf,
+}
+
+impl BlobCache {
+    pub fn new(dir: PathBuf) -> Self {
+        BlobCache { dir }
+    }
+
+    /// Opens the cache in the user's cache directory.
+    ///
+    /// Pruning scans the whole directory, so it is left to the end of an
+    /// operation, see [`BlobCache::prune`].
+    pub fn open_default() -> Result<Self, Error> {
+        let dir = dirs::cache_dir()
+            .ok_or_else(|| Error::Message("Could not find cache directory".to_string()))?
+            .join("rmapi/blobs");
+        Ok(Self::new(dir))
+    }
+
+    pub fn get(&self, hash: &str) -> Option<Vec<u8>> {
+        let path = self.blob_path(hash)?;
+        let data = fs::read(&path).ok()?;
+        // The modification time doubles as the last use for eviction
+        if let Ok(file) = fs::File::options().write(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        Some(data)
+    }
+
+    pub fn put(&self, hash: &str, data: &[u8]) -> Result<(), Error> {
+        let Some(path) = self.blob_path(hash) else {
+            return Err(Error::Message(format!("Invalid blob hash: {}", hash)));
+        };
+        fs::create_dir_all(&self.dir)?;
+        // Write to a temporary file first so readers never see partial blobs
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, data)?;
+        fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Removes the least recently used blobs until at most `max_size` bytes
+    /// remain.
+    pub fn prune(&self, max_size: u64) -> Result<(), Error> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut blobs = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_file() {
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                blobs.push((modified, metadata.len(), entry.path()));
+            }
+        }
+
+        let mut total: u64 = blobs.iter().map(|(_, len, _)| len).sum();
+        blobs.sort();
+        for (_, len, path) in blobs {
+            if total <= max_size {
+                break;
+            }
+            fs::remove_file(path)?;
+            total -= len;
+        }
+        Ok(())
+    }
+
+    fn blob_path(&self, hash: &str) -> Option<PathBuf> {
+        // Hashes are hex strings; anything else must not escape the cache directory
+        if hash.is_empty() || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        Some(self.dir.join(hash))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blob_cache_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("rmapi-blob-cache-{}", std::process::id()));
+        let cache = BlobCache::new(dir.clone());
+
+        assert_eq!(cache.get("abcdef"), None);
+        cache.put("abcdef", b"data").unwrap();
+        assert_eq!(cache.get("abcdef"), Some(b"data".to_vec()));
+        assert!(cache.put("../escape", b"data").is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_blob_cache_prune() {
+        let dir = std::env::temp_dir().join(format!("rmapi-blob-prune-{}", std::process::id()));
+        let cache = BlobCache::new(dir.clone());
+
+        let now = SystemTime::now();
+        for (i, hash) in ["aa", "bb", "cc"].iter().enumerate() {
+            cache.put(hash, &[0; 10]).unwrap();
+            let file = fs::File::options()
+                .write(true)
+                .open(dir.join(hash))
+                .unwrap();
+            file.set_modified(now - std::time::Duration::from_secs(100 - i as u64))
+                .unwrap();
+        }
+        // Reading a blob makes it the most recently used one
+        assert!(cache.get("aa").is_some());
+
+        cache.prune(20).unwrap();
+        assert!(cache.get("bb").is_none());
+        assert!(cache.get("aa").is_some());
+        assert!(cache.get("cc").is_some());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
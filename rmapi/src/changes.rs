@@ -0,0 +1,226 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+
+/// Parent of documents in the trash, as stored in the metadata
+const TRASH_PARENT: &str = "trash";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    /// Moved to another folder, including the trash
+    Moved,
+    Renamed,
+    /// Anything but the metadata changed, e.g. strokes were added on the tablet
+    ContentChanged,
+}
+
+impl fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Moved => "moved",
+            ChangeKind::Renamed => "renamed",
+            ChangeKind::ContentChanged => "content_changed",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A change between two [`RemoteSnapshot`]s.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChangeEvent {
+    pub kind: ChangeKind,
+    pub id: String,
+    /// Current path, or the last known one for removed entries
+    pub path: PathBuf,
+    /// Path before a move or rename
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous_path: Option<PathBuf>,
+    pub is_folder: bool,
+}
+
+/// What the change detection knows about one root index entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntryState {
+    /// Hash of the docSchema; equal hashes mean nothing changed
+    pub hash: String,
+    pub name: String,
+    /// Parent ID as stored in the metadata ("" for the root)
+    pub parent: String,
+    /// Hash over every file of the document but the metadata
+    pub content: String,
+    pub is_folder: bool,
+}
+
+/// The state of every entry of the root index at one generation.
+#[derive(Debug, Clone, Default)]
+pub struct RemoteSnapshot {
+    pub root_hash: String,
+    pub generation: u64,
+    pub entries: HashMap<String, EntryState>,
+}
+
+impl RemoteSnapshot {
+    /// Lists what changed from `self` to `newer`, ordered by path.
+    ///
+    /// A single entry can yield several events, e.g. when it was renamed
+    /// and moved in one go.
+    pub fn diff(&self, newer: &RemoteSnapshot) -> Vec<ChangeEvent> {
+        let mut events = Vec::new();
+        let event = |kind, id: &str, path, previous_path, entry: &EntryState| ChangeEvent {
+            kind,
+            id: id.to_string(),
+            path,
+            previous_path,
+            is_folder: entry.is_folder,
+        };
+
+        for (id, entry) in &newer.entries {
+            let Some(old) = self.entries.get(id) else {
+                events.push(event(ChangeKind::Added, id, newer.path(id), None, entry));
+                continue;
+            };
+            if old.hash == entry.hash {
+                continue;
+            }
+            let previous = Some(self.path(id));
+            if old.parent != entry.parent {
+                events.push(event(
+                    ChangeKind::Moved,
+                    id,
+                    newer.path(id),
+                    previous.clone(),
+                    entry,
+                ));
+            }
+            if old.name != entry.name {
+                events.push(event(
+                    ChangeKind::Renamed,
+                    id,
+                    newer.path(id),
+                    previous,
+                    entry,
+                ));
+            }
+            if old.content != entry.content {
+                events.push(event(
+                    ChangeKind::ContentChanged,
+                    id,
+                    newer.path(id),
+                    None,
+                    entry,
+                ));
+            }
+        }
+        for (id, entry) in &self.entries {
+            if !newer.entries.contains_key(id) {
+                events.push(event(ChangeKind::Removed, id, self.path(id), None, entry));
+            }
+        }
+
+        events.sort_by(|a, b| a.path.cmp(&b.path));
+        events
+    }
+
+    /// Builds the path of `id` from the names of its ancestors.
+    pub fn path(&self, id: &str) -> PathBuf {
+        let mut names = Vec::new();
+        let mut current = id;
+        // The length check guards against parent cycles in broken metadata
+        while let Some(entry) = self.entries.get(current) {
+            if names.len() > self.entries.len() {
+                break;
+            }
+            names.push(entry.name.as_str());
+            current = &entry.parent;
+        }
+        if current == TRASH_PARENT {
+            names.push(TRASH_PARENT);
+        }
+
+        let mut path = PathBuf::from("/");
+        path.extend(names.iter().rev());
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(hash: &str, name: &str, parent: &str, content: &str) -> EntryState {
+        EntryState {
+            hash: hash.to_string(),
+            name: name.to_string(),
+            parent: parent.to_string(),
+            content: content.to_string(),
+            is_folder: false,
+        }
+    }
+
+    fn snapshot(entries: Vec<(&str, EntryState)>) -> RemoteSnapshot {
+        RemoteSnapshot {
+            entries: entries
+                .into_iter()
+                .map(|(id, entry)| (id.to_string(), entry))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_path() {
+        let snapshot = snapshot(vec![
+            ("f", entry("1", "Folder", "", "")),
+            ("d", entry("2", "Doc", "f", "")),
+            ("t", entry("3", "Old", TRASH_PARENT, "")),
+        ]);
+        assert_eq!(snapshot.path("d"), PathBuf::from("/Folder/Doc"));
+        assert_eq!(snapshot.path("t"), PathBuf::from("/trash/Old"));
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = snapshot(vec![
+            ("f", entry("1", "Folder", "", "")),
+            ("a", entry("2", "A", "", "x")),
+            ("b", entry("3", "B", "", "y")),
+            ("c", entry("4", "C", "", "z")),
+        ]);
+        let new = snapshot(vec![
+            ("f", entry("1", "Folder", "", "")),
+            ("a", entry("5", "A", "f", "x")),
+            ("b", entry("6", "B2", "", "y2")),
+            ("n", entry("7", "New", "", "w")),
+        ]);
+
+        let diff = old.diff(&new);
+        let events: Vec<(ChangeKind, &str)> = diff
+            .iter()
+            .map(|event| (event.kind, event.id.as_str()))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                (ChangeKind::Renamed, "b"),
+                (ChangeKind::ContentChanged, "b"),
+                (ChangeKind::Removed, "c"),
+                (ChangeKind::Moved, "a"),
+                (ChangeKind::Added, "n"),
+            ]
+        );
+
+        let moved = old
+            .diff(&new)
+            .into_iter()
+            .find(|event| event.kind == ChangeKind::Moved)
+            .unwrap();
+        assert_eq!(moved.path, PathBuf::from("/Folder/A"));
+        assert_eq!(moved.previous_path, Some(PathBuf::from("/A")));
+    }
+}
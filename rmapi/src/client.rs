@@ -1,24 +1,86 @@
+use crate::backup::{
+    plan_paths, BackupManifest, BackupReport, ManifestEntry, RemovedPolicy, RestoreReport,
+    ARCHIVE_DIR_NAME,
+};
+use crate::cache::{BlobCache, DEFAULT_MAX_CACHE_SIZE};
+use crate::changes::{ChangeEvent, EntryState, RemoteSnapshot};
 use crate::constants::{
-    DOC_TYPE_DOCUMENT, MIME_TYPE_DOC_SCHEMA, MIME_TYPE_JSON, MIME_TYPE_OCTET_STREAM, MIME_TYPE_PDF,
-    MSG_UNKNOWN_COUNT_0, MSG_UNKNOWN_COUNT_4, ROOT_ID, STORAGE_API_URL_ROOT, TRASH_ID,
+    DOC_TYPE_COLLECTION, DOC_TYPE_DOCUMENT, MIME_TYPE_DOC_SCHEMA, MIME_TYPE_EPUB, MIME_TYPE_JSON,
+    MIME_TYPE_OCTET_STREAM, MIME_TYPE_PDF, MSG_UNKNOWN_COUNT_0, ROOT_ID, STORAGE_API_URL_ROOT,
+    TRASH_ID,
 };
 use crate::endpoints::{
-    fetch_blob, get_files, get_root_info, refresh_user_token, register_client, update_root,
-    upload_blob,
+    fetch_blob, fetch_blob_with_progress, get_files, get_root_info, refresh_user_token,
+    register_client, update_root, upload_blob,
 };
 use crate::error::Error;
-use crate::filesystem::FileSystem;
-use crate::objects::{Document, ExtraMetadata, IndexEntry, V4Content, V4Metadata};
+use crate::filesystem::{sanitize_file_name, FileSystem};
+use crate::objects::{Document, DocumentInfo, ExtraMetadata, IndexEntry, V4Content, V4Metadata};
+use crate::progress::{NoProgress, Progress};
+use crate::search::{IndexedDocument, SearchIndex};
+use crate::text::{self, PageText};
+use crate::upload::{
+    pdf_page_count, plan_upload, remap_content, remap_pagedata, PlannedUpload, ReplaceReport,
+    UploadReport,
+};
+use crate::usage::{SizeBreakdown, SubfileKind};
 use chrono::Utc;
-use futures::stream::{self, StreamExt};
+use futures::stream::{self, Stream, StreamExt};
 use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 use uuid::Uuid;
 use zip;
 
-type BoxedFuture<'a> =
-    std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), Error>> + Send + 'a>>;
+type BoxedFuture<'a, T> =
+    std::pin::Pin<Box<dyn std::future::Future<Output = Result<T, Error>> + Send + 'a>>;
+
+/// Optional settings for newly uploaded documents and folders.
+#[derive(Debug, Clone, Default)]
+pub struct UploadOptions {
+    /// ID to use instead of a freshly generated one
+    pub id: Option<String>,
+    /// Visible name (defaults to the local file name)
+    pub visible_name: Option<String>,
+    pub pinned: bool,
+    pub tags: Vec<String>,
+}
+
+/// What to do when a download would replace an existing local file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictPolicy {
+    /// Replace the existing file
+    Overwrite,
+    /// Keep the existing file and skip the document
+    Skip,
+    /// Write to the first free name such as `Notes (1).pdf`
+    #[default]
+    Rename,
+}
+
+/// Settings for [`RmClient::download_entry`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DownloadOptions {
+    /// Download the contents of folders
+    pub recursive: bool,
+    pub conflict: ConflictPolicy,
+    /// Set the modification time of local files to the document's last modification
+    pub preserve_mtime: bool,
+}
+
+/// A document handled by [`RmClient::download_entry`].
+#[derive(Debug, Clone)]
+pub struct DownloadedFile {
+    /// Visible names leading from the downloaded entry to the document
+    pub remote_path: PathBuf,
+    /// File that was written, or the existing file when the document was skipped
+    pub local_path: PathBuf,
+    pub skipped: bool,
+}
 
 pub struct RmClient {
     pub user_token: String,
@@ -26,6 +88,9 @@ pub struct RmClient {
     pub storage_url: String,
     pub filesystem: FileSystem,
     pub http_client: reqwest::Client,
+    progress: Arc<dyn Progress>,
+    /// Shared by every fetch; `None` when there is no cache directory
+    blob_cache: Option<BlobCache>,
 }
 
 impl RmClient {
@@ -49,6 +114,10 @@ impl RmClient {
             storage_url: STORAGE_API_URL_ROOT.to_string(),
             filesystem,
             http_client,
+            progress: Arc::new(NoProgress),
+            blob_cache: BlobCache::open_default()
+                .inspect_err(|e| log::warn!("Blob cache unavailable: {}", e))
+                .ok(),
         })
     }
 
@@ -60,6 +129,21 @@ impl RmClient {
         RmClient::new(&device_token, Some(&user_token)).await
     }
 
+    /// Reports the progress of later uploads and downloads to `progress`.
+    pub fn set_progress(&mut self, progress: Arc<dyn Progress>) {
+        self.progress = progress;
+    }
+
+    /// Evicts the least recently used blobs beyond [`DEFAULT_MAX_CACHE_SIZE`].
+    /// Meant to be called once an operation is done.
+    pub fn prune_blob_cache(&self) {
+        if let Some(cache) = &self.blob_cache {
+            if let Err(e) = cache.prune(DEFAULT_MAX_CACHE_SIZE) {
+                log::warn!("Failed to prune blob cache: {}", e);
+            }
+        }
+    }
+
     pub async fn refresh_user_token(&mut self) -> Result<(), Error> {
         log::debug!("Refreshing auth token");
         self.user_token = refresh_user_token(&self.http_client, &self.device_token).await?;
@@ -81,25 +165,40 @@ impl RmClient {
             return Ok(self.filesystem.get_all_documents());
         }
 
-        let (docs, hash) =
-            get_files(&self.http_client, &self.storage_url, &self.user_token).await?;
+        let (docs, hash) = get_files(
+            &self.http_client,
+            &self.storage_url,
+            &self.user_token,
+            self.blob_cache.as_ref(),
+        )
+        .await?;
         self.filesystem.save_cache(&hash, &docs)?;
         Ok(docs)
     }
 
     pub async fn delete_entry(&self, doc: &Document) -> Result<(), Error> {
-        log::info!("Deleting document: {} ({})", doc.display_name, doc.id);
+        self.delete_entries(&[doc]).await
+    }
+
+    /// Removes several documents in a single root index update.
+    pub async fn delete_entries(&self, docs: &[&Document]) -> Result<(), Error> {
+        for doc in docs {
+            log::info!("Deleting document: {} ({})", doc.display_name, doc.id);
+        }
 
         self.modify_root_index(|root_entries| {
-            let doc_id_str = doc.id.to_string();
-            if let Some(idx) = root_entries.iter().position(|e| e.id == doc_id_str) {
-                root_entries.remove(idx);
-                Ok(())
-            } else {
-                Err(Error::Message(
-                    "Document not found in root index".to_string(),
-                ))
+            for doc in docs {
+                let doc_id_str = doc.id.to_string();
+                if let Some(idx) = root_entries.iter().position(|e| e.id == doc_id_str) {
+                    root_entries.remove(idx);
+                } else {
+                    return Err(Error::Message(format!(
+                        "Document not found in root index: {}",
+                        doc.display_name
+                    )));
+                }
             }
+            Ok(())
         })
         .await?;
 
@@ -112,28 +211,218 @@ impl RmClient {
         local_path: &std::path::Path,
         parent_id: Option<&str>,
     ) -> Result<(), Error> {
-        let uuid = Uuid::new_v4().to_string();
-        let display_name = local_path
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("Unknown");
+        self.put_document_with(local_path, parent_id, &UploadOptions::default())
+            .await
+    }
+
+    /// Uploads a single PDF or EPUB with `options` and commits it right away.
+    pub async fn put_document_with(
+        &self,
+        local_path: &std::path::Path,
+        parent_id: Option<&str>,
+        options: &UploadOptions,
+    ) -> Result<(), Error> {
+        let size = tokio::fs::metadata(local_path).await?.len();
+        let name = local_path.file_name().unwrap_or_default().to_string_lossy();
+        let entry = self
+            .track_single(
+                &name,
+                size,
+                self.prepare_document(local_path, parent_id, options),
+            )
+            .await?;
+        self.commit_entries(vec![entry]).await
+    }
+
+    /// Reports `transfer` to the progress receiver as the only document,
+    /// with `bytes` bytes of file data.
+    async fn track_single<T>(
+        &self,
+        name: &str,
+        bytes: u64,
+        transfer: impl std::future::Future<Output = Result<T, Error>>,
+    ) -> Result<T, Error> {
+        self.progress.start(1, bytes);
+        let result = transfer.await;
+        self.progress.document_done(name);
+        self.progress.finish();
+        result
+    }
+
+    /// Uploads local files and, with `recursive`, directories as folders
+    /// below `parent_id`.
+    ///
+    /// Unsupported files are skipped and listed in the report. A document
+    /// is only uploaded once the folders above it were, and all new entries
+    /// are committed in a single root update.
+    pub async fn upload_paths(
+        &self,
+        paths: &[PathBuf],
+        parent_id: Option<&str>,
+        recursive: bool,
+    ) -> Result<UploadReport, Error> {
+        let (planned, mut report) = plan_upload(paths, recursive)?;
+        let (folders, documents): (Vec<_>, Vec<_>) =
+            planned.into_iter().partition(|item| item.is_folder);
+
+        let mut new_entries = Vec::new();
+        let mut results: HashMap<String, Result<IndexEntry, Error>> =
+            stream::iter(&folders)
+                .map(|item| async move {
+                    (item.id.clone(), self.prepare_planned(item, parent_id).await)
+                })
+                .buffer_unordered(10)
+                .collect()
+                .await;
+
+        // Folders are planned before their contents, so a failure is known
+        // before any entry below it is looked at
+        let mut failed_folders = HashSet::new();
+        for item in &folders {
+            let result = results
+                .remove(&item.id)
+                .unwrap_or_else(|| Err(Error::Message("Folder was not uploaded".to_string())));
+            let result = match &item.parent {
+                Some(parent) if failed_folders.contains(parent) => Err(Error::Message(
+                    "Parent folder could not be created".to_string(),
+                )),
+                _ => result,
+            };
+            match result {
+                Ok(entry) => {
+                    new_entries.push(entry);
+                    report
+                        .folders
+                        .push((item.local_path.clone(), item.remote_path.clone()));
+                }
+                Err(e) => {
+                    log::error!(
+                        "Failed to create folder {}: {}",
+                        item.local_path.display(),
+                        e
+                    );
+                    failed_folders.insert(item.id.clone());
+                    report.failed.push((item.local_path.clone(), e));
+                }
+            }
+        }
+
+        let (documents, orphans): (Vec<_>, Vec<_>) = documents.into_iter().partition(|item| {
+            item.parent
+                .as_ref()
+                .is_none_or(|parent| !failed_folders.contains(parent))
+        });
+        for item in orphans {
+            report.failed.push((
+                item.local_path,
+                Error::Message("Parent folder could not be created".to_string()),
+            ));
+        }
+
+        let total_bytes = documents
+            .iter()
+            .filter_map(|item| std::fs::metadata(&item.local_path).ok())
+            .map(|metadata| metadata.len())
+            .sum();
+        self.progress.start(documents.len(), total_bytes);
+        let results = stream::iter(&documents)
+            .map(|item| async move {
+                let result = self.prepare_planned(item, parent_id).await;
+                self.progress
+                    .document_done(&item.remote_path.to_string_lossy());
+                (item, result)
+            })
+            .buffer_unordered(10)
+            .collect::<Vec<_>>()
+            .await;
+        self.progress.finish();
+        for (item, result) in results {
+            match result {
+                Ok(entry) => {
+                    new_entries.push(entry);
+                    report
+                        .uploaded
+                        .push((item.local_path.clone(), item.remote_path.clone()));
+                }
+                Err(e) => {
+                    log::error!("Failed to upload {}: {}", item.local_path.display(), e);
+                    report.failed.push((item.local_path.clone(), e));
+                }
+            }
+        }
+
+        self.commit_entries(new_entries).await?;
+        Ok(report)
+    }
+
+    async fn prepare_planned(
+        &self,
+        item: &PlannedUpload,
+        parent_id: Option<&str>,
+    ) -> Result<IndexEntry, Error> {
+        let options = UploadOptions {
+            id: Some(item.id.clone()),
+            ..Default::default()
+        };
+        let parent = item.parent.as_deref().or(parent_id);
+        if item.is_folder {
+            let name = item.remote_path.file_name().unwrap_or_default();
+            self.prepare_folder(&name.to_string_lossy(), parent, &options)
+                .await
+        } else {
+            self.prepare_document(&item.local_path, parent, &options)
+                .await
+        }
+    }
+
+    /// Uploads all blobs of a new PDF or EPUB document and returns its root
+    /// index entry. Nothing is visible until the entry is passed to
+    /// [`RmClient::commit_entries`].
+    pub async fn prepare_document(
+        &self,
+        local_path: &std::path::Path,
+        parent_id: Option<&str>,
+        options: &UploadOptions,
+    ) -> Result<IndexEntry, Error> {
+        let (file_type, mime) = match local_path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("pdf") => ("pdf", MIME_TYPE_PDF),
+            Some(ext) if ext.eq_ignore_ascii_case("epub") => ("epub", MIME_TYPE_EPUB),
+            _ => {
+                return Err(Error::Message(format!(
+                    "Unsupported file type: {}",
+                    local_path.display()
+                )))
+            }
+        };
+
+        let uuid = options
+            .id
+            .clone()
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+        let display_name = options.visible_name.clone().unwrap_or_else(|| {
+            local_path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or("Unknown")
+                .to_string()
+        });
 
         log::info!("Uploading document: {} as {}", display_name, uuid);
 
-        let pdf_data = tokio::fs::read(local_path).await?;
-        let pdf_hash = self.compute_hash(&pdf_data);
-        let pdf_size = pdf_data.len() as u64;
+        let file_data = tokio::fs::read(local_path).await?;
+        let file_hash = self.compute_hash(&file_data);
+        let file_size = file_data.len() as u64;
 
         let timestamp = Utc::now().timestamp_millis().to_string();
 
         let metadata = V4Metadata {
-            visible_name: display_name.to_string(),
+            visible_name: display_name,
             doc_type: DOC_TYPE_DOCUMENT.to_string(),
             parent: self.resolve_parent_id_for_metadata(parent_id.unwrap_or(ROOT_ID)),
             created_time: timestamp.clone(),
             last_modified: timestamp.clone(),
             version: 0,
-            pinned: false,
+            pinned: options.pinned,
             deleted: false,
             metadata_modified: false,
             modified: false,
@@ -143,14 +432,14 @@ impl RmClient {
 
         let content = V4Content {
             extra_metadata: ExtraMetadata::default(),
-            file_type: "pdf".to_string(),
+            file_type: file_type.to_string(),
             last_opened_page: 0,
             line_height: -1,
             margins: 180,
             orientation: "portrait".to_string(),
             page_count: 0,
             pages: vec![],
-            tags: vec![],
+            tags: options.tags.clone(),
             text_scale: 1.0,
             transform: crate::objects::DocumentTransform::new().into_map(),
         };
@@ -163,11 +452,11 @@ impl RmClient {
         let pagedata_size = pagedata_data.len() as u64;
 
         // Upload blobs
-        self.upload_part(&pdf_hash, &uuid, "pdf", &pdf_data, MIME_TYPE_PDF)
+        self.upload_file_part(&file_hash, &uuid, file_type, &file_data, mime)
             .await?;
 
         // Use helper for metadata upload
-        // Note: put_document creates new metadata rather than modifying existing, so we construct it first
+        // Note: new documents get fresh metadata rather than modifying existing, so we construct it first
         let (metadata_hash, metadata_size) = self.upload_metadata(&uuid, &metadata).await?;
 
         self.upload_part(
@@ -188,55 +477,379 @@ impl RmClient {
         .await?;
 
         // Create docSchema using IndexEntry
+        let mut entries = vec![
+            IndexEntry::new(
+                content_hash,
+                MSG_UNKNOWN_COUNT_0.to_string(),
+                format!("{}.content", uuid),
+                content_size,
+            ),
+            IndexEntry::new(
+                metadata_hash,
+                MSG_UNKNOWN_COUNT_0.to_string(),
+                format!("{}.metadata", uuid),
+                metadata_size,
+            ),
+            IndexEntry::new(
+                pagedata_hash,
+                MSG_UNKNOWN_COUNT_0.to_string(),
+                format!("{}.pagedata", uuid),
+                pagedata_size,
+            ),
+            IndexEntry::new(
+                file_hash,
+                MSG_UNKNOWN_COUNT_0.to_string(),
+                format!("{}.{}", uuid, file_type),
+                file_size,
+            ),
+        ];
+
+        self.finish_document(&uuid, parent_id, &mut entries).await
+    }
+
+    /// Uploads a new, empty folder and returns its root index entry.
+    pub async fn prepare_folder(
+        &self,
+        name: &str,
+        parent_id: Option<&str>,
+        options: &UploadOptions,
+    ) -> Result<IndexEntry, Error> {
+        let uuid = options
+            .id
+            .clone()
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+        log::info!("Creating folder: {} as {}", name, uuid);
+
+        let timestamp = Utc::now().timestamp_millis().to_string();
+        let metadata = V4Metadata {
+            visible_name: name.to_string(),
+            doc_type: DOC_TYPE_COLLECTION.to_string(),
+            parent: self.resolve_parent_id_for_metadata(parent_id.unwrap_or(ROOT_ID)),
+            created_time: timestamp.clone(),
+            last_modified: timestamp,
+            version: 0,
+            pinned: options.pinned,
+            deleted: false,
+            metadata_modified: false,
+            modified: false,
+            synced: true,
+            other: std::collections::HashMap::new(),
+        };
+        let (metadata_hash, metadata_size) = self.upload_metadata(&uuid, &metadata).await?;
+
+        let content_json = serde_json::to_vec(&serde_json::json!({ "tags": options.tags }))?;
+        let content_hash = self.compute_hash(&content_json);
+        let content_size = content_json.len() as u64;
+        self.upload_part(
+            &content_hash,
+            &uuid,
+            "content",
+            &content_json,
+            MIME_TYPE_JSON,
+        )
+        .await?;
+
+        let mut entries = vec![
+            IndexEntry::new(
+                content_hash,
+                MSG_UNKNOWN_COUNT_0.to_string(),
+                format!("{}.content", uuid),
+                content_size,
+            ),
+            IndexEntry::new(
+                metadata_hash,
+                MSG_UNKNOWN_COUNT_0.to_string(),
+                format!("{}.metadata", uuid),
+                metadata_size,
+            ),
+        ];
+
+        self.finish_document(&uuid, parent_id, &mut entries).await
+    }
+
+    /// Uploads the contents of an `.rmdoc` archive (as written by
+    /// [`RmClient::download_document`]) and returns its root index entry.
+    ///
+    /// The `.metadata` file is rewritten with the new parent and the name and
+    /// pinned state from `options`; every other file is uploaded unchanged.
+    pub async fn prepare_rmdoc(
+        &self,
+        local_path: &std::path::Path,
+        parent_id: Option<&str>,
+        options: &UploadOptions,
+    ) -> Result<IndexEntry, Error> {
+        let path = local_path.to_path_buf();
+        let files =
+            tokio::task::spawn_blocking(move || -> Result<Vec<(String, Vec<u8>)>, Error> {
+                let file = std::fs::File::open(path)?;
+                let mut archive = zip::ZipArchive::new(file)
+                    .map_err(|e| Error::Message(format!("Invalid rmdoc archive: {}", e)))?;
+                let mut files = Vec::new();
+                for i in 0..archive.len() {
+                    let mut entry = archive
+                        .by_index(i)
+                        .map_err(|e| Error::Message(format!("Invalid rmdoc archive: {}", e)))?;
+                    if entry.is_dir() {
+                        continue;
+                    }
+                    let mut data = Vec::new();
+                    std::io::Read::read_to_end(&mut entry, &mut data)?;
+                    files.push((entry.name().to_string(), data));
+                }
+                Ok(files)
+            })
+            .await
+            .map_err(|e| Error::Message(e.to_string()))??;
+
+        let original_id = files
+            .iter()
+            .find_map(|(name, _)| name.strip_suffix(".metadata"))
+            .map(str::to_string)
+            .ok_or_else(|| Error::Message("Metadata not found in rmdoc archive".to_string()))?;
+        let uuid = options.id.clone().unwrap_or_else(|| original_id.clone());
+        log::info!("Uploading rmdoc: {} as {}", local_path.display(), uuid);
+
         let mut entries = Vec::new();
+        for (name, data) in files {
+            let name = match name.strip_prefix(&original_id) {
+                Some(rest) => format!("{}{}", uuid, rest),
+                None => name,
+            };
+
+            if name.ends_with(".metadata") {
+                let mut metadata: V4Metadata = serde_json::from_slice(&data)
+                    .map_err(|e| Error::Message(format!("Failed to parse metadata: {}", e)))?;
+                metadata.parent = self.resolve_parent_id_for_metadata(parent_id.unwrap_or(ROOT_ID));
+                metadata.pinned = options.pinned;
+                if let Some(visible_name) = &options.visible_name {
+                    metadata.visible_name = visible_name.clone();
+                }
+                let (hash, size) = self.upload_metadata(&uuid, &metadata).await?;
+                entries.push(IndexEntry::new(
+                    hash,
+                    MSG_UNKNOWN_COUNT_0.to_string(),
+                    name,
+                    size,
+                ));
+                continue;
+            }
+
+            let mime = match std::path::Path::new(&name)
+                .extension()
+                .and_then(|e| e.to_str())
+            {
+                Some("pdf") => MIME_TYPE_PDF,
+                Some("epub") => MIME_TYPE_EPUB,
+                Some("content") => MIME_TYPE_JSON,
+                _ => MIME_TYPE_OCTET_STREAM,
+            };
+            let hash = self.compute_hash(&data);
+            upload_blob(
+                &self.http_client,
+                &self.storage_url,
+                &self.user_token,
+                &hash,
+                &name,
+                &data,
+                mime,
+                None,
+            )
+            .await?;
+            entries.push(IndexEntry::new(
+                hash,
+                MSG_UNKNOWN_COUNT_0.to_string(),
+                name,
+                data.len() as u64,
+            ));
+        }
+
+        self.finish_document(&uuid, parent_id, &mut entries).await
+    }
+
+    /// Swaps the PDF of the existing document `doc_id` for `local_path`.
+    ///
+    /// The document keeps its ID, so annotations stay attached to every page
+    /// that the new PDF still has (see [`remap_content`]), and every page
+    /// keeps its template in `.pagedata`. Thumbnails are dropped for the
+    /// tablet to redraw, and the metadata version is bumped.
+    pub async fn replace_document(
+        &self,
+        doc_id: &str,
+        local_path: &Path,
+    ) -> Result<ReplaceReport, Error> {
+        let is_pdf = local_path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"));
+        if !is_pdf {
+            return Err(Error::Message(format!(
+                "Only PDF files can replace a document: {}",
+                local_path.display()
+            )));
+        }
+        let file_data = tokio::fs::read(local_path).await?;
+        let pdf_pages = pdf_page_count(&file_data)?;
+
+        let (_, _, root_entries) = self.fetch_root_index().await?;
+        let entry = root_entries
+            .into_iter()
+            .find(|e| e.id == doc_id)
+            .ok_or_else(|| Error::Message("Document not found in root index".to_string()))?;
+        let mut subfiles = self.fetch_doc_schema(&entry.hash).await?;
 
-        entries.push(IndexEntry::new(
-            content_hash.clone(),
-            MSG_UNKNOWN_COUNT_0.to_string(),
-            format!("{}.content", uuid),
-            content_size,
-        ));
-        entries.push(IndexEntry::new(
-            metadata_hash.clone(),
-            MSG_UNKNOWN_COUNT_0.to_string(),
-            format!("{}.metadata", uuid),
-            metadata_size,
-        ));
-        entries.push(IndexEntry::new(
-            pagedata_hash.clone(),
-            MSG_UNKNOWN_COUNT_0.to_string(),
-            format!("{}.pagedata", uuid),
-            pagedata_size,
-        ));
-        entries.push(IndexEntry::new(
-            pdf_hash.clone(),
-            MSG_UNKNOWN_COUNT_0.to_string(),
-            format!("{}.pdf", uuid),
-            pdf_size,
-        ));
-
-        // Use helper for docSchema upload
-        let doc_hash = self.upload_doc_schema(&uuid, &mut entries).await?;
-
-        // Update Root
-
-        let total_size = pdf_size + metadata_size + content_size + pagedata_size;
-        let parent_id_str = parent_id.unwrap_or(ROOT_ID);
-        let index_parent = self.resolve_parent_id_for_index(parent_id_str);
-
-        let mut new_entry = IndexEntry::new(doc_hash, index_parent.clone(), uuid, total_size);
-        new_entry.unknown_count = MSG_UNKNOWN_COUNT_4.to_string();
+        let pdf_name = format!("{}.pdf", doc_id);
+        let pdf_idx = subfiles
+            .iter()
+            .position(|e| e.id == pdf_name)
+            .ok_or_else(|| Error::Message("Only PDF documents can be replaced".to_string()))?;
+        let content_idx = subfiles
+            .iter()
+            .position(|e| e.id.ends_with(".content"))
+            .ok_or_else(|| Error::Message("Content not found in doc schema".to_string()))?;
+        let metadata_idx = subfiles
+            .iter()
+            .position(|e| e.id.ends_with(".metadata"))
+            .ok_or_else(|| Error::Message("Metadata not found in doc schema".to_string()))?;
+
+        let content_bytes = fetch_blob(
+            &self.http_client,
+            &self.storage_url,
+            &self.user_token,
+            &subfiles[content_idx].hash,
+        )
+        .await?;
+        // Edited as plain JSON so fields this crate does not model survive
+        let mut content: serde_json::Value = serde_json::from_slice(&content_bytes)
+            .map_err(|e| Error::Message(format!("Failed to parse content: {}", e)))?;
+        let removed_pages = remap_content(&mut content, pdf_pages);
+
+        let metadata_bytes = fetch_blob(
+            &self.http_client,
+            &self.storage_url,
+            &self.user_token,
+            &subfiles[metadata_idx].hash,
+        )
+        .await?;
+        let mut metadata: V4Metadata = serde_json::from_slice(&metadata_bytes)
+            .map_err(|e| Error::Message(format!("Failed to parse metadata: {}", e)))?;
+        metadata.version += 1;
+        metadata.last_modified = Utc::now().timestamp_millis().to_string();
+
+        let file_hash = self.compute_hash(&file_data);
+        let name = local_path.file_name().unwrap_or_default().to_string_lossy();
+        self.track_single(
+            &name,
+            file_data.len() as u64,
+            self.upload_file_part(&file_hash, doc_id, "pdf", &file_data, MIME_TYPE_PDF),
+        )
+        .await?;
+        subfiles[pdf_idx].hash = file_hash;
+        subfiles[pdf_idx].size = file_data.len() as u64;
+
+        let content_json = serde_json::to_vec(&content)?;
+        // Both include pages inserted on the tablet
+        let old_pages = text::page_order(&content_bytes);
+        let new_pages = text::page_order(&content_json);
+        let content_hash = self.compute_hash(&content_json);
+        self.upload_part(
+            &content_hash,
+            doc_id,
+            "content",
+            &content_json,
+            MIME_TYPE_JSON,
+        )
+        .await?;
+        subfiles[content_idx].hash = content_hash;
+        subfiles[content_idx].size = content_json.len() as u64;
+
+        // The page templates have to line up with the pages as well
+        if let Some(pagedata_idx) = subfiles.iter().position(|e| e.id.ends_with(".pagedata")) {
+            let pagedata_bytes = fetch_blob(
+                &self.http_client,
+                &self.storage_url,
+                &self.user_token,
+                &subfiles[pagedata_idx].hash,
+            )
+            .await?;
+            let pagedata = remap_pagedata(
+                &String::from_utf8_lossy(&pagedata_bytes),
+                &old_pages,
+                &new_pages,
+            );
+            let pagedata_hash = self.compute_hash(pagedata.as_bytes());
+            self.upload_part(
+                &pagedata_hash,
+                doc_id,
+                "pagedata",
+                pagedata.as_bytes(),
+                MIME_TYPE_OCTET_STREAM,
+            )
+            .await?;
+            subfiles[pagedata_idx].hash = pagedata_hash;
+            subfiles[pagedata_idx].size = pagedata.len() as u64;
+        }
+
+        let (metadata_hash, metadata_size) = self.upload_metadata(doc_id, &metadata).await?;
+        subfiles[metadata_idx].hash = metadata_hash;
+        subfiles[metadata_idx].size = metadata_size;
+
+        // Annotations of removed pages and all thumbnails no longer match the PDF
+        let thumbnails = format!("{}.thumbnails/", doc_id);
+        subfiles.retain(|e| {
+            !e.id.starts_with(&thumbnails)
+                && !removed_pages
+                    .iter()
+                    .any(|page| e.id.contains(&format!("/{}", page)))
+        });
+
+        let doc_hash = self.upload_doc_schema(doc_id, &mut subfiles).await?;
+        let mut updated_entry = entry;
+        updated_entry.hash = doc_hash;
+        updated_entry.size = subfiles.iter().map(|s| s.size).sum();
+        updated_entry.unknown_count = subfiles.len().to_string();
+        self.commit_entries(vec![updated_entry]).await?;
+
+        Ok(ReplaceReport {
+            pages_before: old_pages.len() as u32,
+            pages_after: new_pages.len() as u32,
+            removed_pages,
+        })
+    }
+
+    /// Adds previously prepared entries to the root index in a single update.
+    /// Entries replace existing ones with the same ID.
+    pub async fn commit_entries(&self, entries: Vec<IndexEntry>) -> Result<(), Error> {
+        if entries.is_empty() {
+            return Ok(());
+        }
 
         self.modify_root_index(move |root_entries| {
-            root_entries.push(new_entry);
+            for entry in entries {
+                root_entries.retain(|e| e.id != entry.id);
+                root_entries.push(entry);
+            }
             Ok(())
         })
-        .await?;
+        .await
+    }
 
-        Ok(())
+    /// Uploads the docSchema for `subfiles` and builds the matching root index entry.
+    async fn finish_document(
+        &self,
+        uuid: &str,
+        parent_id: Option<&str>,
+        subfiles: &mut [IndexEntry],
+    ) -> Result<IndexEntry, Error> {
+        let doc_hash = self.upload_doc_schema(uuid, subfiles).await?;
+
+        let total_size = subfiles.iter().map(|s| s.size).sum();
+        let index_parent = self.resolve_parent_id_for_index(parent_id.unwrap_or(ROOT_ID));
+
+        let mut new_entry = IndexEntry::new(doc_hash, index_parent, uuid.to_string(), total_size);
+        new_entry.unknown_count = subfiles.len().to_string();
+        Ok(new_entry)
     }
 
-    async fn fetch_root_index(&self) -> Result<(String, u64, Vec<IndexEntry>), Error> {
+    pub async fn fetch_root_index(&self) -> Result<(String, u64, Vec<IndexEntry>), Error> {
         let root_info =
             get_root_info(&self.http_client, &self.storage_url, &self.user_token).await?;
         let root_hash = root_info.hash;
@@ -273,6 +886,23 @@ impl RmClient {
             .collect::<Result<_, _>>()
     }
 
+    /// Returns the tags stored in the `.content` file of a document's docSchema.
+    pub async fn fetch_document_tags(&self, schema_hash: &str) -> Result<Vec<String>, Error> {
+        let subfiles = self.fetch_doc_schema(schema_hash).await?;
+        let Some(content_entry) = subfiles.iter().find(|e| e.id.ends_with(".content")) else {
+            return Ok(Vec::new());
+        };
+
+        let content_bytes = fetch_blob(
+            &self.http_client,
+            &self.storage_url,
+            &self.user_token,
+            &content_entry.hash,
+        )
+        .await?;
+        Ok(V4Content::tags_from_json(&content_bytes))
+    }
+
     async fn upload_doc_schema(
         &self,
         doc_id: &str,
@@ -327,6 +957,7 @@ impl RmClient {
             "root.docSchema",
             new_root_bytes.as_slice(),
             MIME_TYPE_DOC_SCHEMA,
+            None,
         )
         .await?;
 
@@ -508,15 +1139,42 @@ impl RmClient {
             &format!("{}.{}", uuid, ext),
             data,
             mime,
+            None,
+        )
+        .await
+    }
+
+    /// Like `upload_part`, reporting the bytes sent to the progress receiver.
+    async fn upload_file_part(
+        &self,
+        hash: &str,
+        uuid: &str,
+        ext: &str,
+        data: &[u8],
+        mime: &str,
+    ) -> Result<(), Error> {
+        upload_blob(
+            &self.http_client,
+            &self.storage_url,
+            &self.user_token,
+            hash,
+            &format!("{}.{}", uuid, ext),
+            data,
+            mime,
+            Some(&self.progress),
         )
         .await
     }
 
+    /// Downloads a document next to `target_basename`, which gets the
+    /// extension of the downloaded file. Returns the local path and whether
+    /// anything was written.
     pub async fn download_document(
         &self,
         doc_id: &Uuid,
         target_basename: &std::path::Path,
-    ) -> Result<std::path::PathBuf, Error> {
+        conflict: ConflictPolicy,
+    ) -> Result<(std::path::PathBuf, bool), Error> {
         let doc_id_str = doc_id.to_string();
         log::info!("Downloading document: {}", doc_id_str);
 
@@ -529,8 +1187,31 @@ impl RmClient {
             .map(|e| e.hash.clone())
             .ok_or_else(|| Error::Message("Document not found in root index".to_string()))?;
 
+        self.write_document(&entry_hash, target_basename, conflict)
+            .await
+    }
+
+    /// Downloads the document described by the docSchema blob `schema_hash`,
+    /// replacing any existing file.
+    pub async fn download_document_schema(
+        &self,
+        schema_hash: &str,
+        target_basename: &std::path::Path,
+    ) -> Result<std::path::PathBuf, Error> {
+        let (path, _) = self
+            .write_document(schema_hash, target_basename, ConflictPolicy::Overwrite)
+            .await?;
+        Ok(path)
+    }
+
+    async fn write_document(
+        &self,
+        schema_hash: &str,
+        target_basename: &std::path::Path,
+        conflict: ConflictPolicy,
+    ) -> Result<(std::path::PathBuf, bool), Error> {
         // 4 & 5. Fetch docSchema and parse
-        let subfiles_entries = self.fetch_doc_schema(&entry_hash).await?;
+        let subfiles_entries = self.fetch_doc_schema(schema_hash).await?;
         let subfiles: Vec<(String, String)> = subfiles_entries
             .into_iter()
             .map(|entry| (entry.hash, entry.id))
@@ -540,35 +1221,59 @@ impl RmClient {
         let main_file = subfiles
             .iter()
             .find(|(_, name)| name.ends_with(".pdf") || name.ends_with(".epub"));
-
-        if let Some((hash, name)) = main_file {
-            let ext = std::path::Path::new(name)
+        let ext = match main_file {
+            Some((_, name)) => std::path::Path::new(name)
                 .extension()
                 .and_then(|e| e.to_str())
-                .unwrap_or("pdf");
-            let output_path = target_basename.with_extension(ext);
-            log::info!("Downloading single file to {:?}", output_path);
-
-            let data =
-                fetch_blob(&self.http_client, &self.storage_url, &self.user_token, hash).await?;
-            tokio::fs::write(&output_path, data).await?;
-            Ok(output_path)
-        } else {
-            let output_path = target_basename.with_extension("rmdoc");
+                .unwrap_or("pdf"),
+            None => "rmdoc",
+        };
+
+        // Names like "Report v2.1" keep their dots, so siblings do not collide
+        let requested_path = append_extension(target_basename, ext);
+        let Some((output_path, file)) = open_output(&requested_path, conflict)? else {
+            log::info!("{:?} already exists, skipping", requested_path);
+            return Ok((requested_path, false));
+        };
+
+        let result = async {
+            if let Some((hash, _)) = main_file {
+                log::info!("Downloading single file to {:?}", output_path);
+                let data = fetch_blob_with_progress(
+                    &self.http_client,
+                    &self.storage_url,
+                    &self.user_token,
+                    hash,
+                    self.progress.as_ref(),
+                )
+                .await?;
+                tokio::task::spawn_blocking(move || {
+                    let mut file = file;
+                    file.write_all(&data)
+                })
+                .await
+                .map_err(|e| Error::Message(e.to_string()))??;
+                return Ok(());
+            }
+
             log::info!("Creating rmdoc at {:?}", output_path);
 
             // Fetch all blobs
             let mut blob_data = Vec::new();
             for (hash, name) in &subfiles {
-                let data = fetch_blob(&self.http_client, &self.storage_url, &self.user_token, hash)
-                    .await?;
+                let data = fetch_blob_with_progress(
+                    &self.http_client,
+                    &self.storage_url,
+                    &self.user_token,
+                    hash,
+                    self.progress.as_ref(),
+                )
+                .await?;
                 blob_data.push((name.clone(), data));
             }
 
             // Write ZIP (blocking)
-            let path_clone = output_path.clone();
             tokio::task::spawn_blocking(move || -> Result<(), std::io::Error> {
-                let file = std::fs::File::create(&path_clone)?;
                 let mut zip = zip::ZipWriter::new(file);
                 let options = zip::write::FileOptions::default()
                     .compression_method(zip::CompressionMethod::Stored);
@@ -582,18 +1287,49 @@ impl RmClient {
             })
             .await
             .map_err(|e| Error::Message(e.to_string()))??;
+            Ok::<(), Error>(())
+        }
+        .await;
 
-            Ok(output_path)
+        if let Err(e) = result {
+            // Do not leave a truncated file behind
+            let _ = tokio::fs::remove_file(&output_path).await;
+            return Err(e);
         }
+        Ok((output_path, true))
     }
 
+    /// Downloads a document or folder into the local directory `target_path`.
+    ///
+    /// Local names are sanitised visible names. Existing files are handled
+    /// according to `options.conflict`, while existing folders are merged into.
     pub fn download_entry<'a>(
         &'a self,
         node: &'a crate::objects::Node,
         target_path: std::path::PathBuf,
-        recursive: bool,
-    ) -> Result<BoxedFuture<'a>, Error> {
-        if node.is_directory() && !recursive {
+        options: DownloadOptions,
+    ) -> Result<BoxedFuture<'a, Vec<DownloadedFile>>, Error> {
+        let download = self.download_node(node, target_path, options)?;
+        Ok(Box::pin(async move {
+            let documents = if node.is_directory() {
+                node.document_count()
+            } else {
+                1
+            };
+            self.progress.start(documents, node.total_size());
+            let result = download.await;
+            self.progress.finish();
+            result
+        }))
+    }
+
+    fn download_node<'a>(
+        &'a self,
+        node: &'a crate::objects::Node,
+        target_path: std::path::PathBuf,
+        options: DownloadOptions,
+    ) -> Result<BoxedFuture<'a, Vec<DownloadedFile>>, Error> {
+        if node.is_directory() && !options.recursive {
             return Err(Error::Message(format!(
                 "{} is a directory. Use -r to download recursively.",
                 node.name()
@@ -601,31 +1337,954 @@ impl RmClient {
         }
 
         Ok(Box::pin(async move {
+            let local_name = sanitize_file_name(node.name());
+            let mut downloaded = Vec::new();
             if node.is_directory() {
-                let dir_name = node.name();
-                let new_dir = target_path.join(dir_name);
+                let new_dir = target_path.join(local_name);
                 tokio::fs::create_dir_all(&new_dir).await?;
                 log::info!("Created directory {:?}", new_dir);
 
                 let futures = node
                     .children
                     .values()
-                    .map(|child| self.download_entry(child, new_dir.clone(), true))
+                    .map(|child| self.download_node(child, new_dir.clone(), options))
                     .collect::<Result<Vec<_>, _>>()?;
 
-                stream::iter(futures)
+                let children = stream::iter(futures)
                     .buffer_unordered(10)
-                    .collect::<Vec<Result<(), Error>>>()
+                    .collect::<Vec<Result<Vec<DownloadedFile>, Error>>>()
                     .await
                     .into_iter()
-                    .collect::<Result<Vec<()>, Error>>()?;
+                    .collect::<Result<Vec<_>, Error>>()?;
+                for file in children.into_iter().flatten() {
+                    downloaded.push(DownloadedFile {
+                        remote_path: Path::new(node.name()).join(file.remote_path),
+                        ..file
+                    });
+                }
+
+                // Written last, as creating the children touches the folder
+                if options.preserve_mtime {
+                    set_mtime(&new_dir, node.document.last_modified)?;
+                }
             } else {
-                let target_base = target_path.join(node.name());
-                self.download_document(&node.document.id, &target_base)
+                let target_base = target_path.join(local_name);
+                let (local_path, written) = self
+                    .download_document(&node.document.id, &target_base, options.conflict)
                     .await?;
-                log::info!("Downloaded {}", node.name());
+                if written {
+                    if options.preserve_mtime {
+                        set_mtime(&local_path, node.document.last_modified)?;
+                    }
+                    log::info!("Downloaded {}", node.name());
+                }
+                self.progress.document_done(node.name());
+                downloaded.push(DownloadedFile {
+                    remote_path: PathBuf::from(node.name()),
+                    local_path,
+                    skipped: !written,
+                });
             }
-            Ok(())
+            Ok(downloaded)
+        }))
+    }
+
+    /// Mirrors every document of the account into `dir`.
+    ///
+    /// A manifest in `dir` remembers the docSchema hash each local file was
+    /// written from, so later runs only download documents that changed and
+    /// deal with the ones that were removed according to `removed_policy`.
+    pub async fn backup(
+        &self,
+        dir: &Path,
+        removed_policy: RemovedPolicy,
+    ) -> Result<BackupReport, Error> {
+        tokio::fs::create_dir_all(dir).await?;
+        let previous = BackupManifest::load(dir)?;
+        let (root_hash, generation, root_entries) = self.fetch_root_index().await?;
+        let hashes: HashMap<&str, &str> = root_entries
+            .iter()
+            .map(|e| (e.id.as_str(), e.hash.as_str()))
+            .collect();
+
+        let mut manifest = BackupManifest {
+            root_hash,
+            generation,
+            updated: Some(Utc::now()),
+            entries: BTreeMap::new(),
+        };
+        let mut report = BackupReport::default();
+        let mut pending = Vec::new();
+        let mut renames = Vec::new();
+
+        for (rel_path, node) in plan_paths(&self.filesystem.tree) {
+            let id = node.id();
+            let Some(hash) = hashes.get(id.as_str()) else {
+                log::warn!("{} is not in the root index, skipping", node.name());
+                continue;
+            };
+            let mut entry = ManifestEntry {
+                hash: hash.to_string(),
+                name: node.name().to_string(),
+                parent: node.document.parent.clone(),
+                doc_type: node.document.doc_type.clone(),
+                pinned: node.document.bookmarked,
+                tags: Vec::new(),
+                path: rel_path,
+            };
+
+            if node.is_directory() {
+                tokio::fs::create_dir_all(dir.join(&entry.path)).await?;
+                manifest.entries.insert(id, entry);
+                continue;
+            }
+
+            match previous.entries.get(&id) {
+                Some(old) if old.hash == entry.hash && dir.join(&old.path).is_file() => {
+                    // Same content, but an ancestor folder may have been renamed
+                    if let Some(ext) = old.path.extension().and_then(|e| e.to_str()) {
+                        entry.path = append_extension(&entry.path, ext);
+                    }
+                    entry.tags = old.tags.clone();
+                    if entry.path != old.path {
+                        renames.push((old.path.clone(), entry.path.clone()));
+                    } else {
+                        report.unchanged += 1;
+                    }
+                    manifest.entries.insert(id, entry);
+                }
+                _ => pending.push((id, entry)),
+            }
+        }
+
+        // Removed documents are dealt with first, as a new or renamed
+        // document may take their path
+        let archive_dir = dir
+            .join(ARCHIVE_DIR_NAME)
+            .join(Utc::now().format("%Y%m%dT%H%M%S").to_string());
+        let current_ids: HashSet<&str> = manifest
+            .entries
+            .keys()
+            .chain(pending.iter().map(|(id, _)| id))
+            .map(String::as_str)
+            .collect();
+        report.removed =
+            retire_removed(dir, &previous, &current_ids, removed_policy, &archive_dir)?;
+
+        // Before downloading, which may reuse a path a renamed file left
+        apply_renames(dir, &renames)?;
+        report.moved.extend(renames);
+
+        let results = stream::iter(pending)
+            .map(|(id, mut entry)| async move {
+                let result = async {
+                    let target = dir.join(&entry.path);
+                    let path = self.download_document_schema(&entry.hash, &target).await?;
+                    entry.tags = self.fetch_document_tags(&entry.hash).await?;
+                    entry.path = path
+                        .strip_prefix(dir)
+                        .map(Path::to_path_buf)
+                        .unwrap_or(path);
+                    Ok::<_, Error>(())
+                }
+                .await;
+                (id, entry, result)
+            })
+            .buffer_unordered(10)
+            .collect::<Vec<_>>()
+            .await;
+
+        for (id, entry, result) in results {
+            match result {
+                Ok(()) => {
+                    report.downloaded.push(entry.path.clone());
+                    manifest.entries.insert(id, entry);
+                }
+                Err(e) => {
+                    log::error!("Failed to back up {}: {}", entry.path.display(), e);
+                    // Keep the old entry so the next run retries instead of pruning it
+                    if let Some(old) = previous.entries.get(&id) {
+                        manifest.entries.insert(id, old.clone());
+                    }
+                    report.failed.push((entry.path, e));
+                }
+            }
+        }
+
+        // Clean up files of changed documents that were written elsewhere
+        let current_paths: HashSet<&PathBuf> = manifest.entries.values().map(|e| &e.path).collect();
+        let mut stale_dirs = Vec::new();
+        for (id, old) in &previous.entries {
+            if current_paths.contains(&old.path) {
+                continue;
+            }
+            if old.doc_type == crate::objects::DocumentType::Collection {
+                stale_dirs.push(dir.join(&old.path));
+                continue;
+            }
+
+            let old_path = dir.join(&old.path);
+            if manifest.entries.contains_key(id) && old_path.is_file() {
+                tokio::fs::remove_file(&old_path).await?;
+            }
+        }
+
+        // Deepest folders first, and only once they are empty
+        stale_dirs.sort_by_key(|d| std::cmp::Reverse(d.components().count()));
+        for stale_dir in stale_dirs {
+            let _ = tokio::fs::remove_dir(stale_dir).await;
+        }
+
+        manifest.save(dir)?;
+        Ok(report)
+    }
+
+    /// Re-creates the folders and documents recorded in the backup manifest in `dir`.
+    ///
+    /// With `subtree`, a path relative to the backup directory, only that
+    /// folder or document and everything below it is restored. Entries keep
+    /// their original IDs, and anything whose docSchema hash or ID is
+    /// already in the root index is skipped, so a restore can safely be
+    /// repeated. Entries whose parent is not restored are placed below
+    /// `into_id` when given, otherwise below their original parent if it
+    /// still exists. Entries below a folder that could not be restored are
+    /// reported as failed. All new entries are committed in a single root
+    /// update.
+    pub async fn restore_backup(
+        &self,
+        dir: &Path,
+        subtree: Option<&Path>,
+        into_id: Option<&str>,
+    ) -> Result<RestoreReport, Error> {
+        let manifest = BackupManifest::load(dir)?;
+        if manifest.entries.is_empty() {
+            return Err(Error::Message(format!(
+                "No backup manifest found in {}",
+                dir.display()
+            )));
+        }
+        let subtree = subtree.map(|path| path.strip_prefix("/").unwrap_or(path));
+        let selected: BTreeMap<&String, &ManifestEntry> = manifest
+            .entries
+            .iter()
+            .filter(|(_, entry)| subtree.is_none_or(|subtree| entry.path.starts_with(subtree)))
+            .collect();
+        if let (Some(subtree), true) = (subtree, selected.is_empty()) {
+            return Err(Error::Message(format!(
+                "Nothing in the backup at {}",
+                subtree.display()
+            )));
+        }
+
+        let (_, _, root_entries) = self.fetch_root_index().await?;
+        let remote_hashes: HashSet<&str> = root_entries.iter().map(|e| e.hash.as_str()).collect();
+        let remote_ids: HashSet<&str> = root_entries.iter().map(|e| e.id.as_str()).collect();
+
+        let mut report = RestoreReport::default();
+        let mut folders = Vec::new();
+        let mut documents = Vec::new();
+        for (&id, &entry) in &selected {
+            if remote_hashes.contains(entry.hash.as_str()) || remote_ids.contains(id.as_str()) {
+                report.skipped += 1;
+                continue;
+            }
+
+            // IDs are reused, so parents restored in this run can be referenced directly
+            let parent_id = if selected.contains_key(&entry.parent) {
+                Some(entry.parent.as_str())
+            } else {
+                into_id.or_else(|| {
+                    remote_ids
+                        .contains(entry.parent.as_str())
+                        .then_some(entry.parent.as_str())
+                })
+            };
+            if entry.doc_type == crate::objects::DocumentType::Collection {
+                folders.push((id, entry, parent_id));
+            } else {
+                documents.push((id, entry, parent_id));
+            }
+        }
+
+        let mut new_entries = Vec::new();
+        let mut folder_results = stream::iter(folders)
+            .map(|(id, entry, parent_id)| async move {
+                (
+                    id,
+                    entry,
+                    self.prepare_restored(dir, id, entry, parent_id).await,
+                )
+            })
+            .buffer_unordered(10)
+            .collect::<Vec<_>>()
+            .await;
+        // Parents come before their children, so a failure is known before
+        // any entry below it is looked at
+        folder_results.sort_by_key(|(_, entry, _)| entry.path.components().count());
+        let mut failed_folders = HashSet::new();
+        for (id, entry, result) in folder_results {
+            let result = if failed_folders.contains(&entry.parent) {
+                Err(Error::Message(
+                    "Parent folder could not be restored".to_string(),
+                ))
+            } else {
+                result
+            };
+            match result {
+                Ok(index_entry) => {
+                    new_entries.push(index_entry);
+                    report.restored.push(entry.path.clone());
+                }
+                Err(e) => {
+                    log::error!("Failed to restore {}: {}", entry.path.display(), e);
+                    failed_folders.insert(id.clone());
+                    report.failed.push((entry.path.clone(), e));
+                }
+            }
+        }
+
+        let (documents, orphans): (Vec<_>, Vec<_>) = documents
+            .into_iter()
+            .partition(|(_, entry, _)| !failed_folders.contains(&entry.parent));
+        for (_, entry, _) in orphans {
+            report.failed.push((
+                entry.path.clone(),
+                Error::Message("Parent folder could not be restored".to_string()),
+            ));
+        }
+
+        let results = stream::iter(documents)
+            .map(|(id, entry, parent_id)| async move {
+                (
+                    id,
+                    entry,
+                    self.prepare_restored(dir, id, entry, parent_id).await,
+                )
+            })
+            .buffer_unordered(10)
+            .collect::<Vec<_>>()
+            .await;
+        for (_, entry, result) in results {
+            match result {
+                Ok(index_entry) => {
+                    new_entries.push(index_entry);
+                    report.restored.push(entry.path.clone());
+                }
+                Err(e) => {
+                    log::error!("Failed to restore {}: {}", entry.path.display(), e);
+                    report.failed.push((entry.path.clone(), e));
+                }
+            }
+        }
+
+        self.commit_entries(new_entries).await?;
+        Ok(report)
+    }
+
+    async fn prepare_restored(
+        &self,
+        dir: &Path,
+        id: &str,
+        entry: &ManifestEntry,
+        parent_id: Option<&str>,
+    ) -> Result<IndexEntry, Error> {
+        let options = UploadOptions {
+            id: Some(id.to_string()),
+            visible_name: Some(entry.name.clone()),
+            pinned: entry.pinned,
+            tags: entry.tags.clone(),
+        };
+        let local_path = dir.join(&entry.path);
+        if entry.doc_type == crate::objects::DocumentType::Collection {
+            self.prepare_folder(&entry.name, parent_id, &options).await
+        } else if local_path.extension() == Some("rmdoc".as_ref()) {
+            self.prepare_rmdoc(&local_path, parent_id, &options).await
+        } else {
+            self.prepare_document(&local_path, parent_id, &options)
+                .await
+        }
+    }
+
+    /// Fetches a blob, serving it from the local blob cache when possible.
+    pub async fn fetch_blob_cached(&self, hash: &str) -> Result<Vec<u8>, Error> {
+        let cache = self.blob_cache.as_ref();
+        if let Some(data) = cache.and_then(|cache| cache.get(hash)) {
+            return Ok(data);
+        }
+
+        let data = fetch_blob(&self.http_client, &self.storage_url, &self.user_token, hash).await?;
+        if let Some(Err(e)) = cache.map(|cache| cache.put(hash, &data)) {
+            log::warn!("Failed to cache blob {}: {}", hash, e);
+        }
+        Ok(data)
+    }
+
+    /// Like `fetch_doc_schema`, but served from the blob cache when possible.
+    async fn fetch_doc_schema_cached(&self, hash: &str) -> Result<Vec<IndexEntry>, Error> {
+        let schema_bytes = self.fetch_blob_cached(hash).await?;
+        String::from_utf8(schema_bytes)?
+            .lines()
+            .skip(1)
+            .filter(|line| !line.is_empty())
+            .map(IndexEntry::from_str)
+            .collect()
+    }
+
+    /// Collects the metadata, content summary and docSchema of a document.
+    pub async fn document_info(&self, id: &str) -> Result<DocumentInfo, Error> {
+        let (_, _, root_entries) = self.fetch_root_index().await?;
+        let entry = root_entries
+            .into_iter()
+            .find(|e| e.id == id)
+            .ok_or_else(|| Error::Message(format!("{} is not in the root index", id)))?;
+        let subfiles = self.fetch_doc_schema_cached(&entry.hash).await?;
+
+        let metadata_entry = subfiles
+            .iter()
+            .find(|e| e.id.ends_with(".metadata"))
+            .ok_or_else(|| Error::Message(format!("{} has no metadata", id)))?;
+        let metadata_bytes = self.fetch_blob_cached(&metadata_entry.hash).await?;
+        let raw_metadata: serde_json::Value = serde_json::from_slice(&metadata_bytes)?;
+        let metadata: V4Metadata = serde_json::from_value(raw_metadata.clone())?;
+
+        // The typed `V4Content` is too strict for older files, so only pick
+        // out the fields that are shown.
+        let mut file_type = None;
+        let mut page_count = None;
+        let mut tags = Vec::new();
+        if let Some(content_entry) = subfiles.iter().find(|e| e.id.ends_with(".content")) {
+            let content_bytes = self.fetch_blob_cached(&content_entry.hash).await?;
+            let content: serde_json::Value = serde_json::from_slice(&content_bytes)?;
+            file_type = content
+                .get("fileType")
+                .and_then(|t| t.as_str())
+                .filter(|t| !t.is_empty())
+                .map(str::to_string);
+            if metadata.doc_type != DOC_TYPE_COLLECTION {
+                page_count = V4Content::page_count_from_json(&content_bytes);
+            }
+            tags = V4Content::tags_from_json(&content_bytes);
+        }
+
+        let created = metadata.created();
+        let last_modified = metadata.modified();
+        Ok(DocumentInfo {
+            id: entry.id,
+            name: metadata.visible_name,
+            parent: metadata.parent,
+            doc_type: metadata.doc_type,
+            version: metadata.version,
+            created,
+            last_modified,
+            pinned: metadata.pinned,
+            file_type,
+            page_count,
+            tags,
+            hash: entry.hash,
+            size: entry.size,
+            subfiles,
+            metadata: raw_metadata,
+        })
+    }
+
+    /// Returns how much storage each of `ids` uses, broken down by subfile kind.
+    ///
+    /// IDs that are not in the root index are left out of the result.
+    pub async fn storage_usage(
+        &self,
+        ids: &[String],
+    ) -> Result<HashMap<String, SizeBreakdown>, Error> {
+        let (_, _, root_entries) = self.fetch_root_index().await?;
+        let hashes: HashMap<&str, &str> = root_entries
+            .iter()
+            .map(|e| (e.id.as_str(), e.hash.as_str()))
+            .collect();
+
+        let results = stream::iter(
+            ids.iter()
+                .filter_map(|id| hashes.get(id.as_str()).map(|hash| (id, *hash))),
+        )
+        .map(|(id, hash)| async move { (id, self.fetch_doc_schema_cached(hash).await) })
+        .buffer_unordered(10)
+        .collect::<Vec<_>>()
+        .await;
+
+        let mut usage = HashMap::new();
+        for (id, result) in results {
+            let mut breakdown = SizeBreakdown::default();
+            for subfile in result? {
+                breakdown.add(SubfileKind::from_file_name(&subfile.id), subfile.size);
+            }
+            usage.insert(id.clone(), breakdown);
+        }
+        Ok(usage)
+    }
+
+    /// Polls the root index every `interval` and yields what changed.
+    ///
+    /// The last state seen is kept in `snapshot`. When it is `None` the first
+    /// poll only records the current state; otherwise changes since then are
+    /// reported, so a new stream resumes where a dropped one stopped, e.g.
+    /// after refreshing an expired token. Errors are yielded without ending
+    /// the stream, so a network hiccup does not stop a watcher.
+    pub fn watch_changes<'a>(
+        &'a self,
+        interval: Duration,
+        snapshot: &'a mut Option<RemoteSnapshot>,
+    ) -> impl Stream<Item = Result<ChangeEvent, Error>> + 'a {
+        struct WatchState<'a> {
+            snapshot: &'a mut Option<RemoteSnapshot>,
+            pending: VecDeque<ChangeEvent>,
+            polled: bool,
+        }
+        let state = WatchState {
+            snapshot,
+            pending: VecDeque::new(),
+            polled: false,
+        };
+
+        stream::unfold(state, move |mut state| async move {
+            loop {
+                if let Some(event) = state.pending.pop_front() {
+                    return Some((Ok(event), state));
+                }
+                if state.polled {
+                    tokio::time::sleep(interval).await;
+                }
+                state.polled = true;
+
+                match self.remote_snapshot(state.snapshot.as_ref()).await {
+                    Ok(None) => {}
+                    Ok(Some(snapshot)) => {
+                        if let Some(previous) = state.snapshot.as_ref() {
+                            state.pending.extend(previous.diff(&snapshot));
+                        }
+                        *state.snapshot = Some(snapshot);
+                    }
+                    Err(e) => return Some((Err(e), state)),
+                }
+            }
+        })
+    }
+
+    /// Records the state of every root index entry, or returns `None` when
+    /// the root did not change since `previous`.
+    ///
+    /// Only entries whose docSchema hash changed are fetched again, and
+    /// blobs come from the blob cache when possible. Entries that cannot be
+    /// read are logged and keep their previous state, if any.
+    pub async fn remote_snapshot(
+        &self,
+        previous: Option<&RemoteSnapshot>,
+    ) -> Result<Option<RemoteSnapshot>, Error> {
+        let root_info =
+            get_root_info(&self.http_client, &self.storage_url, &self.user_token).await?;
+        if previous.is_some_and(|previous| previous.root_hash == root_info.hash) {
+            return Ok(None);
+        }
+
+        let (root_hash, generation, root_entries) = self.fetch_root_index().await?;
+        let mut entries = HashMap::new();
+        let mut changed = Vec::new();
+        for entry in root_entries {
+            let known = previous
+                .and_then(|previous| previous.entries.get(&entry.id))
+                .filter(|known| known.hash == entry.hash);
+            match known {
+                Some(known) => {
+                    entries.insert(entry.id, known.clone());
+                }
+                None => changed.push(entry),
+            }
+        }
+
+        let results = stream::iter(changed)
+            .map(|entry| async move {
+                let state = self.entry_state(&entry.hash).await;
+                (entry.id, state)
+            })
+            .buffer_unordered(10)
+            .collect::<Vec<_>>()
+            .await;
+        let mut complete = true;
+        for (id, state) in results {
+            match state {
+                // Deleted documents can linger in the index until the tablet syncs
+                Ok(state) => entries.extend(state.map(|state| (id, state))),
+                Err(e) if e.is_unauthorized() => return Err(e),
+                Err(e) => {
+                    // Keeping the previous state avoids reporting it as removed
+                    log::warn!("Skipping entry {}: {}", id, e);
+                    complete = false;
+                    if let Some(known) = previous.and_then(|previous| previous.entries.get(&id)) {
+                        entries.insert(id, known.clone());
+                    }
+                }
+            }
+        }
+
+        // Without the root hash the next poll tries the skipped entries again
+        let root_hash = if complete { root_hash } else { String::new() };
+        Ok(Some(RemoteSnapshot {
+            root_hash,
+            generation,
+            entries,
         }))
     }
+
+    async fn entry_state(&self, hash: &str) -> Result<Option<EntryState>, Error> {
+        let subfiles = self.fetch_doc_schema_cached(hash).await?;
+        let metadata_entry = subfiles
+            .iter()
+            .find(|e| e.id.ends_with(".metadata"))
+            .ok_or_else(|| Error::Message("Metadata not found in doc schema".to_string()))?;
+        let metadata: V4Metadata =
+            serde_json::from_slice(&self.fetch_blob_cached(&metadata_entry.hash).await?)
+                .map_err(|e| Error::Message(format!("Failed to parse metadata: {}", e)))?;
+        if metadata.deleted {
+            return Ok(None);
+        }
+
+        let content: String = subfiles
+            .iter()
+            .filter(|e| !e.id.ends_with(".metadata"))
+            .map(|e| e.hash.as_str())
+            .collect();
+        Ok(Some(EntryState {
+            hash: hash.to_string(),
+            name: metadata.visible_name,
+            parent: metadata.parent,
+            content: self.compute_hash(content.as_bytes()),
+            is_folder: metadata.doc_type == DOC_TYPE_COLLECTION,
+        }))
+    }
+
+    /// Makes sure `index` holds up-to-date text for `doc_ids`.
+    ///
+    /// Only documents whose docSchema hash differs from the indexed one are
+    /// downloaded again. Documents that were deleted are dropped from the index.
+    pub async fn update_search_index(
+        &self,
+        index: &mut SearchIndex,
+        doc_ids: &[String],
+    ) -> Result<(), Error> {
+        let (_, _, root_entries) = self.fetch_root_index().await?;
+        let hashes: HashMap<&str, &str> = root_entries
+            .iter()
+            .map(|e| (e.id.as_str(), e.hash.as_str()))
+            .collect();
+        index.retain_ids(&hashes.keys().copied().collect());
+
+        let stale: Vec<(&String, &str)> = doc_ids
+            .iter()
+            .filter_map(|id| hashes.get(id.as_str()).map(|hash| (id, *hash)))
+            .filter(|(id, hash)| !index.is_fresh(id, hash))
+            .collect();
+
+        let results = stream::iter(stale)
+            .map(|(id, hash)| async move { (id, hash, self.extract_document_text(hash).await) })
+            .buffer_unordered(10)
+            .collect::<Vec<_>>()
+            .await;
+
+        for (id, hash, result) in results {
+            match result {
+                Ok(pages) => index.insert(
+                    id.clone(),
+                    IndexedDocument {
+                        hash: hash.to_string(),
+                        pages,
+                    },
+                ),
+                Err(e) => log::warn!("Failed to index {}: {}", id, e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Extracts the PDF/EPUB text, typed text and highlights of a document, per page.
+    async fn extract_document_text(&self, schema_hash: &str) -> Result<Vec<PageText>, Error> {
+        let subfiles = self.fetch_doc_schema_cached(schema_hash).await?;
+
+        let page_ids = match subfiles.iter().find(|e| e.id.ends_with(".content")) {
+            Some(content) => text::page_order(&self.fetch_blob_cached(&content.hash).await?),
+            None => Vec::new(),
+        };
+        let page_number = |name: &str| -> Option<u32> {
+            let page_id = Path::new(name).file_stem()?.to_str()?;
+            let idx = page_ids.iter().position(|id| id == page_id)?;
+            Some(idx as u32 + 1)
+        };
+
+        let mut pages: BTreeMap<u32, Vec<String>> = BTreeMap::new();
+        for entry in &subfiles {
+            let extracted = if entry.id.ends_with(".pdf") || entry.id.ends_with(".epub") {
+                let data = self.fetch_blob_cached(&entry.hash).await?;
+                let is_pdf = entry.id.ends_with(".pdf");
+                tokio::task::spawn_blocking(move || {
+                    if is_pdf {
+                        text::extract_pdf(&data)
+                    } else {
+                        text::extract_epub(&data)
+                    }
+                })
+                .await
+                .map_err(|e| Error::Message(e.to_string()))??
+            } else if entry.id.ends_with(".rm") || entry.id.ends_with(".json") {
+                let Some(page) = page_number(&entry.id) else {
+                    continue;
+                };
+                let data = self.fetch_blob_cached(&entry.hash).await?;
+                let text = if entry.id.ends_with(".rm") {
+                    text::extract_rm(&data)
+                } else {
+                    text::extract_highlights(&data)
+                };
+                vec![PageText { page, text }]
+            } else {
+                continue;
+            };
+
+            for page in extracted.into_iter().filter(|p| !p.text.trim().is_empty()) {
+                pages.entry(page.page).or_default().push(page.text);
+            }
+        }
+
+        Ok(pages
+            .into_iter()
+            .map(|(page, texts)| PageText {
+                page,
+                text: texts.join("\n"),
+            })
+            .collect())
+    }
+}
+
+/// Opens the file a download is written to, applying `conflict` when `path`
+/// exists. Returns `None` when the download should be skipped.
+fn open_output(
+    path: &Path,
+    conflict: ConflictPolicy,
+) -> std::io::Result<Option<(PathBuf, std::fs::File)>> {
+    if conflict == ConflictPolicy::Overwrite {
+        return Ok(Some((path.to_path_buf(), std::fs::File::create(path)?)));
+    }
+
+    // create_new claims a name atomically, so documents sharing a name that
+    // are downloaded concurrently never end up in the same file
+    let mut candidate = path.to_path_buf();
+    let mut attempt = 0;
+    loop {
+        match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&candidate)
+        {
+            Ok(file) => return Ok(Some((candidate, file))),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                if conflict == ConflictPolicy::Skip {
+                    return Ok(None);
+                }
+                attempt += 1;
+                candidate = numbered_path(path, attempt);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Inserts ` (n)` between the file stem and the extension.
+fn numbered_path(path: &Path, n: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{} ({}).{}", stem, n, ext.to_string_lossy()),
+        None => format!("{} ({})", stem, n),
+    };
+    path.with_file_name(name)
+}
+
+fn set_mtime(path: &Path, modified: chrono::DateTime<Utc>) -> Result<(), Error> {
+    let mtime =
+        filetime::FileTime::from_unix_time(modified.timestamp(), modified.timestamp_subsec_nanos());
+    filetime::set_file_mtime(path, mtime)?;
+    Ok(())
+}
+
+/// Moves backed up files from the first to the second path of each pair,
+/// both relative to `dir`.
+///
+/// Every file is first moved to a temporary name, so renames that swap or
+/// chain names never overwrite a file that has not been moved yet.
+fn apply_renames(dir: &Path, renames: &[(PathBuf, PathBuf)]) -> Result<(), Error> {
+    let mut staged = Vec::new();
+    for (index, (from, to)) in renames.iter().enumerate() {
+        let from = dir.join(from);
+        let file_name = from.file_name().unwrap_or_default().to_string_lossy();
+        let temporary = from.with_file_name(format!(".{}.{}.renaming", file_name, index));
+        std::fs::rename(&from, &temporary)?;
+        staged.push((temporary, dir.join(to)));
+    }
+    for (temporary, to) in staged {
+        if let Some(parent) = to.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::rename(temporary, to)?;
+    }
+    Ok(())
+}
+
+/// Archives or deletes the files of documents in `previous` whose ID is not
+/// in `current_ids`, and returns their paths relative to `dir`.
+fn retire_removed(
+    dir: &Path,
+    previous: &BackupManifest,
+    current_ids: &HashSet<&str>,
+    policy: RemovedPolicy,
+    archive_dir: &Path,
+) -> Result<Vec<PathBuf>, Error> {
+    let mut removed = Vec::new();
+    for (id, old) in &previous.entries {
+        if current_ids.contains(id.as_str())
+            || old.doc_type == crate::objects::DocumentType::Collection
+        {
+            continue;
+        }
+        let old_path = dir.join(&old.path);
+        if !old_path.is_file() {
+            continue;
+        }
+        match policy {
+            RemovedPolicy::Delete => std::fs::remove_file(&old_path)?,
+            RemovedPolicy::Archive => {
+                let archived = archive_dir.join(&old.path);
+                if let Some(parent) = archived.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::rename(&old_path, archived)?;
+            }
+        }
+        removed.push(old.path.clone());
+    }
+    Ok(removed)
+}
+
+/// Adds `.ext` to the file name unless it already ends with it. Unlike
+/// `Path::with_extension` this keeps dots in names like "Notes v1.2".
+fn append_extension(path: &Path, ext: &str) -> PathBuf {
+    let has_ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case(ext));
+    if has_ext {
+        return path.to_path_buf();
+    }
+    let mut with_ext = path.as_os_str().to_owned();
+    with_ext.push(".");
+    with_ext.push(ext);
+    PathBuf::from(with_ext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_extension() {
+        assert_eq!(
+            append_extension(Path::new("dir/report.pdf"), "pdf"),
+            PathBuf::from("dir/report.pdf")
+        );
+        assert_eq!(
+            append_extension(Path::new("Notes v1.2"), "rmdoc"),
+            PathBuf::from("Notes v1.2.rmdoc")
+        );
+    }
+
+    #[test]
+    fn test_apply_renames_swap() {
+        let dir = std::env::temp_dir().join(format!("rmapi-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("X.pdf"), "x").unwrap();
+        std::fs::write(dir.join("Y.pdf"), "y").unwrap();
+
+        let renames = [
+            (PathBuf::from("X.pdf"), PathBuf::from("Y.pdf")),
+            (PathBuf::from("Y.pdf"), PathBuf::from("sub/X.pdf")),
+        ];
+        apply_renames(&dir, &renames).unwrap();
+        assert_eq!(std::fs::read_to_string(dir.join("Y.pdf")).unwrap(), "x");
+        assert_eq!(std::fs::read_to_string(dir.join("sub/X.pdf")).unwrap(), "y");
+        assert!(!dir.join("X.pdf").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_retire_removed_before_rename() {
+        let dir = std::env::temp_dir().join(format!("rmapi-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("Old.pdf"), "old").unwrap();
+        std::fs::write(dir.join("Kept.pdf"), "kept").unwrap();
+
+        let entry = |path: &str| ManifestEntry {
+            hash: String::new(),
+            name: String::new(),
+            parent: String::new(),
+            doc_type: crate::objects::DocumentType::Document,
+            pinned: false,
+            tags: Vec::new(),
+            path: PathBuf::from(path),
+        };
+        let previous = BackupManifest {
+            entries: BTreeMap::from([
+                ("removed".to_string(), entry("Old.pdf")),
+                ("kept".to_string(), entry("Kept.pdf")),
+            ]),
+            ..Default::default()
+        };
+        let archive_dir = dir.join(ARCHIVE_DIR_NAME).join("run");
+
+        // The remaining document is renamed to the path of the removed one
+        let removed = retire_removed(
+            &dir,
+            &previous,
+            &HashSet::from(["kept"]),
+            RemovedPolicy::Archive,
+            &archive_dir,
+        )
+        .unwrap();
+        apply_renames(
+            &dir,
+            &[(PathBuf::from("Kept.pdf"), PathBuf::from("Old.pdf"))],
+        )
+        .unwrap();
+
+        assert_eq!(removed, vec![PathBuf::from("Old.pdf")]);
+        assert_eq!(
+            std::fs::read_to_string(archive_dir.join("Old.pdf")).unwrap(),
+            "old"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join("Old.pdf")).unwrap(),
+            "kept"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_numbered_path() {
+        assert_eq!(
+            numbered_path(Path::new("out/Notes.pdf"), 1),
+            PathBuf::from("out/Notes (1).pdf")
+        );
+        assert_eq!(
+            numbered_path(Path::new("Notes.v2.rmdoc"), 3),
+            PathBuf::from("Notes.v2 (3).rmdoc")
+        );
+        assert_eq!(
+            numbered_path(Path::new("Notes"), 2),
+            PathBuf::from("Notes (2)")
+        );
+    }
 }
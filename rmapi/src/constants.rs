@@ -30,6 +30,7 @@ pub const HEADER_X_GOOG_HASH: &str = "x-goog-hash";
 
 // MIME Types
 pub const MIME_TYPE_PDF: &str = "application/pdf";
+pub const MIME_TYPE_EPUB: &str = "application/epub+zip";
 pub const MIME_TYPE_JSON: &str = "application/json";
 pub const MIME_TYPE_OCTET_STREAM: &str = "application/octet-stream";
 pub const MIME_TYPE_DOC_SCHEMA: &str = "text/plain; charset=UTF-8";
@@ -1,19 +1,25 @@
+use crate::cache::BlobCache;
 use crate::constants::{
     DOC_UPLOAD_ENDPOINT, GROUP_AUTH, HEADER_RM_FILENAME, HEADER_RM_META, HEADER_RM_SOURCE,
     HEADER_X_GOOG_HASH, NEW_CLIENT_URL, NEW_TOKEN_URL, ROOT_SYNC_ENDPOINT, STORAGE_API_URL_ROOT,
     STORAGE_DISCOVERY_API_URL, STORAGE_DISCOVERY_API_VERSION, WEBAPP_API_URL_ROOT,
 };
 use crate::error::Error;
-use crate::objects::{ClientRegistration, RootInfo, StorageInfo, V4Entry, V4Metadata};
+use crate::objects::{ClientRegistration, RootInfo, StorageInfo, V4Content, V4Entry, V4Metadata};
+use crate::progress::Progress;
 use base64::Engine;
 use futures::stream::{self, StreamExt};
 use log;
 use reqwest::{self, Body};
 
+use std::sync::Arc;
 use tokio::fs::File;
 use tokio_util::codec::{BytesCodec, FramedRead};
 use uuid::Uuid;
 
+/// Size of the pieces uploads are streamed in when progress is reported
+const UPLOAD_CHUNK_SIZE: usize = 64 * 1024;
+
 pub async fn register_client(http_client: &reqwest::Client, code: &str) -> Result<String, Error> {
     log::info!("Registering client with code: {}", code);
     let registration_info = ClientRegistration {
@@ -199,6 +205,7 @@ pub async fn get_files(
     http_client: &reqwest::Client,
     _storage_url: &str, // Ignored because Sync V4 needs internal host
     user_token: &str,
+    cache: Option<&BlobCache>,
 ) -> Result<(Vec<crate::objects::Document>, String), Error> {
     log::info!("Requesting files version Sync V4");
 
@@ -245,10 +252,12 @@ pub async fn get_files(
     let user_token = user_token.to_string();
     let client = http_client.clone();
 
+    let cache = cache.cloned();
     let documents = stream::iter(entries)
         .map(|entry| {
             let user_token = user_token.clone();
             let client = client.clone();
+            let cache = cache.clone();
             async move {
                 // Fetch .docSchema to find .metadata hash
                 let doc_schema_response = client
@@ -268,13 +277,16 @@ pub async fn get_files(
 
                 let doc_schema_text = doc_schema_response.text().await.ok()?;
                 let mut metadata_hash = None;
+                let mut content_hash = None;
                 for subline in doc_schema_text.lines().skip(1) {
-                    if subline.contains(".metadata") {
-                        let subparts: Vec<&str> = subline.split(':').collect();
-                        if !subparts.is_empty() {
-                            metadata_hash = Some(subparts[0].to_string());
-                            break;
-                        }
+                    let subparts: Vec<&str> = subline.split(':').collect();
+                    if subparts.len() < 3 {
+                        continue;
+                    }
+                    if subparts[2].ends_with(".metadata") {
+                        metadata_hash = Some(subparts[0].to_string());
+                    } else if subparts[2].ends_with(".content") {
+                        content_hash = Some(subparts[0].to_string());
                     }
                 }
 
@@ -297,12 +309,34 @@ pub async fn get_files(
                     return None;
                 }
 
-                let last_modified = metadata_json
-                    .last_modified
-                    .parse::<i64>()
-                    .ok()
-                    .and_then(chrono::DateTime::from_timestamp_millis)
-                    .unwrap_or_default();
+                // Tags and the page count live in .content; a missing or
+                // unreadable one just means no tags and no pages
+                let mut tags = Vec::new();
+                let mut page_count = 0;
+                if let Some(c_hash) = content_hash {
+                    // Blobs never change, so only new .content files cost a request
+                    let cached = cache.as_ref().and_then(|cache| cache.get(&c_hash));
+                    let c_body = match cached {
+                        Some(data) => Some(data),
+                        None => {
+                            let data =
+                                fetch_content(&client, &user_token, &entry.doc_id, &c_hash).await;
+                            if let (Some(cache), Some(data)) = (&cache, &data) {
+                                if let Err(e) = cache.put(&c_hash, data) {
+                                    log::warn!("Failed to cache blob {}: {}", c_hash, e);
+                                }
+                            }
+                            data
+                        }
+                    };
+                    if let Some(c_body) = c_body {
+                        tags = V4Content::tags_from_json(&c_body);
+                        page_count = V4Content::page_count_from_json(&c_body).unwrap_or(0);
+                    }
+                }
+
+                let last_modified = metadata_json.modified().unwrap_or_default();
+                let created = metadata_json.created().unwrap_or_default();
 
                 Some(crate::objects::Document {
                     id: Uuid::parse_str(&entry.doc_id).unwrap_or(Uuid::nil()),
@@ -326,6 +360,10 @@ pub async fn get_files(
                     current_page: 0,
                     bookmarked: metadata_json.pinned,
                     parent: metadata_json.parent,
+                    tags,
+                    size: entry.size,
+                    created,
+                    page_count,
                 })
             }
         })
@@ -337,6 +375,26 @@ pub async fn get_files(
     Ok((documents, root_hash))
 }
 
+/// Downloads the `.content` file of a document, `None` when that fails.
+async fn fetch_content(
+    client: &reqwest::Client,
+    user_token: &str,
+    doc_id: &str,
+    hash: &str,
+) -> Option<Vec<u8>> {
+    let response = client
+        .get(format!("{}/sync/v3/files/{}", STORAGE_API_URL_ROOT, hash))
+        .bearer_auth(user_token)
+        .header(HEADER_RM_FILENAME, format!("{}.content", doc_id))
+        .send()
+        .await
+        .ok()?;
+    if !response.status().is_success() {
+        return None;
+    }
+    response.bytes().await.ok().map(|bytes| bytes.to_vec())
+}
+
 pub async fn fetch_blob(
     http_client: &reqwest::Client,
     base_url: &str,
@@ -354,6 +412,32 @@ pub async fn fetch_blob(
     Ok(bytes.to_vec())
 }
 
+/// Like [`fetch_blob`], reporting every received chunk to `progress`.
+pub async fn fetch_blob_with_progress(
+    http_client: &reqwest::Client,
+    base_url: &str,
+    user_token: &str,
+    hash: &str,
+    progress: &dyn Progress,
+) -> Result<Vec<u8>, Error> {
+    let mut response = http_client
+        .get(format!("{}/sync/v3/files/{}", base_url, hash))
+        .bearer_auth(user_token)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let mut data = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        progress.advance(chunk.len() as u64);
+        data.extend_from_slice(&chunk);
+    }
+    Ok(data)
+}
+
+/// Uploads a blob. With `progress`, the body is streamed in chunks that are
+/// reported as they are sent.
+#[allow(clippy::too_many_arguments)]
 pub async fn upload_blob(
     http_client: &reqwest::Client,
     base_url: &str,
@@ -362,12 +446,28 @@ pub async fn upload_blob(
     filename: &str,
     data: &[u8],
     content_type: &str,
+    progress: Option<&Arc<dyn Progress>>,
 ) -> Result<(), Error> {
     let checksum = crc32c::crc32c(data);
     let checksum_bytes = checksum.to_be_bytes();
     let content_md5 = base64::engine::general_purpose::STANDARD.encode(checksum_bytes);
     let hash_header_value = format!("crc32c={}", content_md5);
 
+    let body = match progress {
+        Some(progress) => {
+            let progress = Arc::clone(progress);
+            let chunks: Vec<Vec<u8>> = data
+                .chunks(UPLOAD_CHUNK_SIZE)
+                .map(|chunk| chunk.to_vec())
+                .collect();
+            Body::wrap_stream(stream::iter(chunks).map(move |chunk| {
+                progress.advance(chunk.len() as u64);
+                Ok::<_, std::io::Error>(chunk)
+            }))
+        }
+        None => Body::from(data.to_vec()),
+    };
+
     let response = http_client
         .put(format!("{}/sync/v3/files/{}", base_url, hash))
         .bearer_auth(user_token)
@@ -375,7 +475,7 @@ pub async fn upload_blob(
         .header(HEADER_X_GOOG_HASH, hash_header_value)
         .header("Content-Type", content_type)
         .header("Content-Length", data.len().to_string())
-        .body(data.to_vec())
+        .body(body)
         .send()
         .await?;
 
@@ -0,0 +1,604 @@
+use crate::error::Error;
+use crate::text::{decode_entities, html_to_text};
+use base64::Engine;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::io::{Cursor, Write};
+use std::path::Path;
+use std::sync::LazyLock;
+use uuid::Uuid;
+
+static TITLE_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<title[^>]*>(.*?)</title>").unwrap());
+static H1_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)<h1[^>]*>(.*?)</h1>").unwrap());
+static LANG_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?is)<html\b[^>]*?\blang\s*=\s*["']?([a-z]{2,8}(?:-[a-z0-9]{1,8})*)"#).unwrap()
+});
+static ATTR_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"([^\s"'>/=]+)(?:\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'=<>`]+)))?"#).unwrap()
+});
+static ID_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[A-Za-z_][A-Za-z0-9_.-]*$").unwrap());
+
+/// Elements copied into the EPUB. Anything else is unwrapped, keeping its
+/// text.
+const KEPT_ELEMENTS: &str = concat!(
+    "a abbr address article aside b bdi bdo blockquote br caption cite code col ",
+    "colgroup dd del details dfn div dl dt em figcaption figure footer h1 h2 h3 h4 h5 ",
+    "h6 header hr i img ins kbd li main mark nav ol p pre q s samp section small span ",
+    "strong sub summary sup table tbody td tfoot th thead time tr u ul var wbr",
+);
+/// Kept elements without an end tag
+const VOID_ELEMENTS: &str = "br col hr img wbr";
+/// Elements left out together with everything inside them. Styles go too,
+/// so the reader's own layout applies.
+const DROPPED_ELEMENTS: &str = concat!(
+    "head script noscript style template iframe object video audio svg math canvas ",
+    "select textarea button",
+);
+/// Opening one of these ends an open paragraph
+const BLOCK_ELEMENTS: &str = concat!(
+    "address article aside blockquote details div dl figure footer h1 h2 h3 h4 h5 h6 ",
+    "header hr main nav ol p pre section table ul",
+);
+
+/// An EPUB built by [`package_html`].
+#[derive(Debug, Clone)]
+pub struct HtmlEpub {
+    pub title: String,
+    pub data: Vec<u8>,
+    /// Number of local images packaged with the article
+    pub images: usize,
+    /// Image sources that are not local files; their alt text is shown instead
+    pub skipped_images: Vec<String>,
+}
+
+/// Packages an HTML file and the local images it references into an EPUB 3.
+///
+/// The title is taken from `title`, the `<title>` or first `<h1>` of the
+/// page, or the file name, in that order. The markup is cleaned up into
+/// XHTML: scripts, styles, forms and embedded media are left out, and
+/// links are only kept when they point to the web.
+pub fn package_html(path: &Path, title: Option<&str>) -> Result<HtmlEpub, Error> {
+    let data = std::fs::read(path)?;
+    let html = String::from_utf8_lossy(&data);
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    let fallback = path.file_stem().unwrap_or_default().to_string_lossy();
+    build_epub(&html, base_dir, title, &fallback)
+}
+
+fn build_epub(
+    html: &str,
+    base_dir: &Path,
+    title: Option<&str>,
+    fallback_title: &str,
+) -> Result<HtmlEpub, Error> {
+    let title = title
+        .map(str::to_string)
+        .or_else(|| detect_title(html))
+        .unwrap_or_else(|| fallback_title.to_string());
+    let lang = LANG_RE
+        .captures(html)
+        .map(|caps| caps[1].to_string())
+        .unwrap_or_else(|| "en".to_string());
+
+    let mut body = XhtmlBody::new(base_dir);
+    body.convert(html);
+
+    let data = write_epub(&title, &lang, &body)
+        .map_err(|e| Error::Message(format!("Failed to write EPUB: {}", e)))?;
+    Ok(HtmlEpub {
+        title,
+        data,
+        images: body.images.len(),
+        skipped_images: body.skipped_images,
+    })
+}
+
+fn detect_title(html: &str) -> Option<String> {
+    [&TITLE_RE, &H1_RE].iter().find_map(|re| {
+        let caps = re.captures(html)?;
+        let title = html_to_text(&caps[1])
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        (!title.is_empty()).then_some(title)
+    })
+}
+
+struct PackagedImage {
+    /// Path inside the EPUB, relative to the article
+    name: String,
+    media_type: &'static str,
+    data: Vec<u8>,
+}
+
+/// Rewrites HTML into the body of a well-formed XHTML document, collecting
+/// the images it shows.
+struct XhtmlBody<'a> {
+    base_dir: &'a Path,
+    out: String,
+    /// Kept elements that are open
+    open: Vec<&'static str>,
+    ids: HashSet<String>,
+    images: Vec<PackagedImage>,
+    image_names: HashMap<String, String>,
+    skipped_images: Vec<String>,
+}
+
+impl<'a> XhtmlBody<'a> {
+    fn new(base_dir: &'a Path) -> Self {
+        XhtmlBody {
+            base_dir,
+            out: String::new(),
+            open: Vec::new(),
+            ids: HashSet::new(),
+            images: Vec::new(),
+            image_names: HashMap::new(),
+            skipped_images: Vec::new(),
+        }
+    }
+
+    fn convert(&mut self, html: &str) {
+        let mut rest = html;
+        while let Some(start) = rest.find('<') {
+            self.text(&rest[..start]);
+            rest = self.markup(&rest[start..]);
+        }
+        self.text(rest);
+        while !self.open.is_empty() {
+            self.close_top();
+        }
+    }
+
+    /// Handles the markup `rest` starts with and returns what follows it.
+    fn markup<'h>(&mut self, rest: &'h str) -> &'h str {
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            return comment.find("-->").map_or("", |end| &comment[end + 3..]);
+        }
+        let after = &rest[1..];
+        if after.starts_with(['!', '?']) {
+            return after.find('>').map_or("", |end| &after[end + 1..]);
+        }
+        let is_end = after.starts_with('/');
+        let name_start = after.strip_prefix('/').unwrap_or(after);
+        if !name_start.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            // A `<` that does not start a tag is text
+            self.text("<");
+            return after;
+        }
+        let name_len = name_start
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '-' && c != ':')
+            .unwrap_or(name_start.len());
+        let name = name_start[..name_len].to_ascii_lowercase();
+        let (tag, remaining) = split_tag(&name_start[name_len..]);
+
+        if is_end {
+            self.end_tag(&name);
+            remaining
+        } else if is_listed(DROPPED_ELEMENTS, &name) {
+            if tag.trim_end().ends_with('/') {
+                return remaining;
+            }
+            match find_end_tag(remaining, &name) {
+                Some(end) => {
+                    let closing = &remaining[end..];
+                    closing.find('>').map_or("", |end| &closing[end + 1..])
+                }
+                None => "",
+            }
+        } else {
+            self.start_tag(&name, tag);
+            remaining
+        }
+    }
+
+    fn start_tag(&mut self, name: &str, tag: &str) {
+        let Some(name) = KEPT_ELEMENTS.split_whitespace().find(|kept| *kept == name) else {
+            return;
+        };
+        self.close_implied(name);
+        let attributes = parse_attributes(tag);
+        if name == "img" {
+            self.image(&attributes);
+            return;
+        }
+
+        self.out.push('<');
+        self.out.push_str(name);
+        for (attribute, value) in attributes {
+            let value = match attribute.as_str() {
+                "href" | "cite" if is_web_link(&value) => value,
+                "id" if ID_RE.is_match(&value) && self.ids.insert(value.clone()) => value,
+                "reversed" => "reversed".to_string(),
+                "title" | "lang" | "dir" | "colspan" | "rowspan" | "start" | "span"
+                | "datetime" => value,
+                _ => continue,
+            };
+            self.out
+                .push_str(&format!(" {}=\"{}\"", attribute, escape(&value)));
+        }
+        if is_listed(VOID_ELEMENTS, name) {
+            self.out.push_str("/>");
+        } else {
+            self.out.push('>');
+            self.open.push(name);
+        }
+    }
+
+    /// Closes what HTML closes implicitly, e.g. a paragraph when the next
+    /// one starts.
+    fn close_implied(&mut self, name: &str) {
+        let closes: &[&str] = match name {
+            "li" => &["li"],
+            "dt" | "dd" => &["dt", "dd"],
+            "td" | "th" => &["td", "th"],
+            "tr" => &["td", "th", "tr"],
+            _ if is_listed(BLOCK_ELEMENTS, name) => &["p"],
+            _ => &[],
+        };
+        while self.open.last().is_some_and(|top| closes.contains(top)) {
+            self.close_top();
+        }
+    }
+
+    fn end_tag(&mut self, name: &str) {
+        if let Some(index) = self.open.iter().rposition(|open| *open == name) {
+            while self.open.len() > index {
+                self.close_top();
+            }
+        }
+    }
+
+    fn close_top(&mut self) {
+        if let Some(name) = self.open.pop() {
+            self.out.push_str(&format!("</{}>", name));
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        self.out.push_str(&escape(&decode_entities(text)));
+    }
+
+    fn image(&mut self, attributes: &[(String, String)]) {
+        let attribute = |name: &str| {
+            attributes
+                .iter()
+                .find(|(attribute, _)| attribute == name)
+                .map(|(_, value)| value.as_str())
+        };
+        let alt = attribute("alt").unwrap_or_default();
+        let Some(src) = attribute("src") else {
+            self.out.push_str(&escape(alt));
+            return;
+        };
+        match self.package_image(src) {
+            Some(name) => self.out.push_str(&format!(
+                "<img src=\"{}\" alt=\"{}\"/>",
+                escape(&name),
+                escape(alt)
+            )),
+            None => {
+                let shown: String = src.chars().take(80).collect();
+                self.skipped_images.push(shown);
+                self.out.push_str(&escape(alt));
+            }
+        }
+    }
+
+    /// Adds the image `src` points to, a local file or a `data:` URI, and
+    /// returns its name inside the EPUB.
+    fn package_image(&mut self, src: &str) -> Option<String> {
+        if let Some(name) = self.image_names.get(src) {
+            return Some(name.clone());
+        }
+        let (ext, data) = if let Some(uri) = src.strip_prefix("data:") {
+            let (header, payload) = uri.split_once(',')?;
+            let mime = header.strip_suffix(";base64")?;
+            let ext = mime.strip_prefix("image/")?.replace("svg+xml", "svg");
+            let data = base64::engine::general_purpose::STANDARD
+                .decode(payload.trim())
+                .ok()?;
+            (ext, data)
+        } else {
+            if src.contains("://") || src.starts_with("//") {
+                return None;
+            }
+            let relative = percent_decode(src.split(['?', '#']).next()?);
+            let path = self.base_dir.join(relative);
+            let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+            (ext, std::fs::read(&path).ok()?)
+        };
+        let media_type = image_media_type(&ext)?;
+
+        let name = format!("images/image{}.{}", self.images.len() + 1, ext);
+        self.images.push(PackagedImage {
+            name: name.clone(),
+            media_type,
+            data,
+        });
+        self.image_names.insert(src.to_string(), name.clone());
+        Some(name)
+    }
+}
+
+fn is_listed(elements: &str, name: &str) -> bool {
+    elements.split_whitespace().any(|element| element == name)
+}
+
+/// Position of the first `</name` in `text`, ignoring case.
+fn find_end_tag(text: &str, name: &str) -> Option<usize> {
+    let closing = format!("</{}", name);
+    text.match_indices("</")
+        .map(|(index, _)| index)
+        .find(|&index| {
+            text.as_bytes()
+                .get(index..index + closing.len())
+                .is_some_and(|candidate| candidate.eq_ignore_ascii_case(closing.as_bytes()))
+        })
+}
+
+/// Splits the rest of a tag at its closing `>`, skipping quoted values.
+fn split_tag(tag: &str) -> (&str, &str) {
+    let mut quote = None;
+    for (index, c) in tag.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), _) if c == open => quote = None,
+            (None, '>') => return (&tag[..index], &tag[index + 1..]),
+            _ => {}
+        }
+    }
+    (tag, "")
+}
+
+fn parse_attributes(tag: &str) -> Vec<(String, String)> {
+    ATTR_RE
+        .captures_iter(tag)
+        .map(|caps| {
+            let value = caps
+                .get(2)
+                .or_else(|| caps.get(3))
+                .or_else(|| caps.get(4))
+                .map_or("", |value| value.as_str());
+            (caps[1].to_ascii_lowercase(), decode_entities(value))
+        })
+        .collect()
+}
+
+fn is_web_link(href: &str) -> bool {
+    ["http://", "https://", "mailto:", "#"]
+        .iter()
+        .any(|prefix| href.starts_with(prefix))
+}
+
+fn image_media_type(ext: &str) -> Option<&'static str> {
+    match ext {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "svg" => Some("image/svg+xml"),
+        "webp" => Some("image/webp"),
+        _ => None,
+    }
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = bytes
+            .get(index + 1..index + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .filter(|_| bytes[index] == b'%')
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match hex {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Escapes text and attribute values, dropping characters XML does not allow.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn write_epub(title: &str, lang: &str, body: &XhtmlBody) -> zip::result::ZipResult<Vec<u8>> {
+    let title = escape(title);
+    let modified = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ");
+
+    let article = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xml:lang="{lang}" lang="{lang}">
+<head>
+<meta charset="UTF-8"/>
+<title>{title}</title>
+</head>
+<body>
+{body}
+</body>
+</html>
+"#,
+        body = body.out.trim(),
+    );
+    let nav = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" xml:lang="{lang}" lang="{lang}">
+<head>
+<meta charset="UTF-8"/>
+<title>{title}</title>
+</head>
+<body>
+<nav epub:type="toc" id="toc">
+<ol>
+<li><a href="article.xhtml">{title}</a></li>
+</ol>
+</nav>
+</body>
+</html>
+"#
+    );
+    let image_items: String = body
+        .images
+        .iter()
+        .enumerate()
+        .map(|(index, image)| {
+            format!(
+                "    <item id=\"image{}\" href=\"{}\" media-type=\"{}\"/>\n",
+                index + 1,
+                image.name,
+                image.media_type
+            )
+        })
+        .collect();
+    let package = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="uid" xml:lang="{lang}">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="uid">urn:uuid:{uuid}</dc:identifier>
+    <dc:title>{title}</dc:title>
+    <dc:language>{lang}</dc:language>
+    <meta property="dcterms:modified">{modified}</meta>
+  </metadata>
+  <manifest>
+    <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+    <item id="article" href="article.xhtml" media-type="application/xhtml+xml"/>
+{image_items}  </manifest>
+  <spine>
+    <itemref idref="article"/>
+  </spine>
+</package>
+"#,
+        uuid = Uuid::new_v4(),
+    );
+    let container = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    // Readers recognize the format by an uncompressed first entry
+    let stored =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    let deflated =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    zip.start_file("mimetype", stored)?;
+    zip.write_all(b"application/epub+zip")?;
+
+    let files = [
+        ("META-INF/container.xml", container.as_bytes()),
+        ("OEBPS/content.opf", package.as_bytes()),
+        ("OEBPS/nav.xhtml", nav.as_bytes()),
+        ("OEBPS/article.xhtml", article.as_bytes()),
+    ];
+    for (name, data) in files {
+        zip.start_file(name, deflated)?;
+        zip.write_all(data)?;
+    }
+    for image in &body.images {
+        zip.start_file(format!("OEBPS/{}", image.name), stored)?;
+        zip.write_all(&image.data)?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn convert(html: &str) -> String {
+        let mut body = XhtmlBody::new(Path::new(""));
+        body.convert(html);
+        body.out
+    }
+
+    #[test]
+    fn test_convert_to_xhtml() {
+        assert_eq!(
+            convert("<P CLASS=intro>Fish &amp; chips<br>1 < 2<p>Caf&eacute; &foo;"),
+            "<p>Fish &amp; chips<br/>1 &lt; 2</p><p>Café &amp;foo;</p>"
+        );
+        assert_eq!(
+            convert("<ul><li>One<li><b>Two</ul><script>if (a < b) {}</script>x"),
+            "<ul><li>One</li><li><b>Two</b></li></ul>x"
+        );
+        assert_eq!(
+            convert(r#"<a href="https://example.com/?a=1&amp;b=2" onclick="x()">web</a>"#),
+            r#"<a href="https://example.com/?a=1&amp;b=2">web</a>"#
+        );
+        assert_eq!(
+            convert(r#"<custom-tag><a href="other.html" id="1x">local</a></custom-tag>"#),
+            "<a>local</a>"
+        );
+        assert_eq!(
+            convert(r#"<img src="https://example.com/a.png" alt="A chart">"#),
+            "A chart"
+        );
+    }
+
+    #[test]
+    fn test_build_epub() {
+        let html = r#"<!DOCTYPE html><html lang="de"><head><title>Fish &amp; Chips</title>
+            <style>p { color: red }</style></head>
+            <body><h1>Heading</h1><p>Text <img src="data:image/png;base64,iVBORw0KGgo=" alt="x"></p></body></html>"#;
+        let epub = build_epub(html, Path::new(""), None, "fallback").unwrap();
+        assert_eq!(epub.title, "Fish & Chips");
+        assert_eq!(epub.images, 1);
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(epub.data)).unwrap();
+        let mimetype = archive.by_index(0).unwrap();
+        assert_eq!(mimetype.name(), "mimetype");
+        assert_eq!(mimetype.compression(), zip::CompressionMethod::Stored);
+        drop(mimetype);
+
+        let mut package = String::new();
+        archive
+            .by_name("OEBPS/content.opf")
+            .unwrap()
+            .read_to_string(&mut package)
+            .unwrap();
+        assert!(package.contains("<dc:title>Fish &amp; Chips</dc:title>"));
+        assert!(package.contains("<dc:language>de</dc:language>"));
+        assert!(package.contains(r#"href="images/image1.png" media-type="image/png""#));
+
+        let mut article = String::new();
+        archive
+            .by_name("OEBPS/article.xhtml")
+            .unwrap()
+            .read_to_string(&mut article)
+            .unwrap();
+        assert!(article
+            .contains(r#"<h1>Heading</h1><p>Text <img src="images/image1.png" alt="x"/></p>"#));
+        assert!(!article.contains("color: red"));
+
+        let untitled = build_epub("<p>Plain</p>", Path::new(""), None, "notes").unwrap();
+        assert_eq!(untitled.title, "notes");
+    }
+}
@@ -1,15 +1,40 @@
+use crate::constants::TRASH_ID;
 use crate::error::Error;
-use crate::objects::{Document, FileTree, Node};
+use crate::objects::{Document, FileTree, Node, NodeKind};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::{Component, Path, PathBuf};
 
+/// Prefix of a path component that selects a document by (a prefix of) its ID
+pub const ID_SELECTOR: &str = "id:";
+
+/// Bumped whenever `Document` gains fields that older caches do not contain.
+const CACHE_VERSION: u32 = 3;
+
 #[derive(Serialize, Deserialize)]
 struct CacheData {
+    #[serde(default)]
+    version: u32,
     hash: String,
     documents: Vec<Document>,
 }
 
+/// Filters for [`FileSystem::find`]. Every set filter has to match.
+#[derive(Debug, Clone, Default)]
+pub struct FindQuery {
+    /// Glob pattern (`*`, `?`, `[...]`) matched against the visible name
+    pub name: Option<String>,
+    pub kind: Option<NodeKind>,
+    pub modified_after: Option<DateTime<Utc>>,
+    pub modified_before: Option<DateTime<Utc>>,
+    /// Only return pinned entries
+    pub pinned: bool,
+    /// Tags that all have to be present
+    pub tags: Vec<String>,
+}
+
+#[derive(Clone)]
 pub struct FileSystem {
     pub tree: FileTree,
     pub current_hash: String,
@@ -38,6 +63,10 @@ impl FileSystem {
         if cache_path.exists() {
             let data = fs::read_to_string(cache_path)?;
             let cache: CacheData = serde_json::from_str(&data)?;
+            if cache.version != CACHE_VERSION {
+                log::info!("Cache format changed, rebuilding");
+                return Ok(FileSystem::new());
+            }
             Ok(FileSystem {
                 tree: FileTree::build(cache.documents.clone()),
                 current_hash: cache.hash,
@@ -59,6 +88,7 @@ impl FileSystem {
         self.tree = FileTree::build(self.docs.clone());
 
         let cache = CacheData {
+            version: CACHE_VERSION,
             hash: self.current_hash.clone(),
             documents: self.docs.clone(),
         };
@@ -77,19 +107,40 @@ impl FileSystem {
             .join("rmapi/tree.cache"))
     }
 
-    pub fn list_dir(&self, path: Option<&Path>) -> Result<Vec<&Node>, Error> {
+    /// Lists the children of a directory together with the name they can be
+    /// addressed by (see [`Node::labeled_children`]).
+    pub fn list_dir(&self, path: Option<&Path>) -> Result<Vec<(String, &Node)>, Error> {
         let target = path.unwrap_or(&self.current_path);
         let node = self.find_node_by_path(target)?;
-        let mut entries: Vec<&Node> = node.children.values().collect();
+        Ok(node.listing())
+    }
 
-        // Sort entries: directories first, then files, both alphabetically
-        entries.sort_by(|a, b| match (a.is_directory(), b.is_directory()) {
-            (true, false) => std::cmp::Ordering::Less,
-            (false, true) => std::cmp::Ordering::Greater,
-            _ => a.name().to_lowercase().cmp(&b.name().to_lowercase()),
-        });
+    /// Completes the last component of a partially typed path.
+    ///
+    /// `partial` is taken relative to `cwd`. The returned candidates keep the
+    /// directory part as typed and end directories with a `/`.
+    pub fn complete_path(&self, partial: &str, cwd: &Path) -> Vec<String> {
+        let (dir_part, prefix) = match partial.rfind('/') {
+            Some(idx) => partial.split_at(idx + 1),
+            None => ("", partial),
+        };
+        let dir = if dir_part.is_empty() {
+            cwd.to_path_buf()
+        } else {
+            normalize_path(Path::new(dir_part), cwd)
+        };
+        let Ok(node) = self.find_node_by_path(&dir) else {
+            return Vec::new();
+        };
 
-        Ok(entries)
+        node.listing()
+            .into_iter()
+            .filter(|(label, _)| label.starts_with(prefix))
+            .map(|(label, child)| {
+                let suffix = if child.is_directory() { "/" } else { "" };
+                format!("{}{}{}", dir_part, label, suffix)
+            })
+            .collect()
     }
 
     pub fn cd(&mut self, path: &Path) -> Result<(), Error> {
@@ -111,7 +162,23 @@ impl FileSystem {
         &self.current_path
     }
 
+    /// Resolves `path` to a node.
+    ///
+    /// A component can pick one of several same-named siblings with a
+    /// `name@<id-prefix>` selector. Without one, an ambiguous name resolves to
+    /// the most recently modified sibling; use
+    /// [`FileSystem::find_node_by_path_strict`] where guessing is not acceptable.
     pub fn find_node_by_path(&self, path: &Path) -> Result<&Node, Error> {
+        self.resolve_path(path, false)
+    }
+
+    /// Like [`FileSystem::find_node_by_path`], but fails when a name is ambiguous.
+    /// Destructive operations should use this.
+    pub fn find_node_by_path_strict(&self, path: &Path) -> Result<&Node, Error> {
+        self.resolve_path(path, true)
+    }
+
+    fn resolve_path(&self, path: &Path, strict: bool) -> Result<&Node, Error> {
         let normalized_path = normalize_path(path, Path::new("/"));
 
         // Check if root
@@ -122,37 +189,290 @@ impl FileSystem {
             return Ok(&self.tree.root);
         }
 
-        let mut current = &self.tree.root;
+        let (mut current, rest) = self.start_node(&normalized_path)?;
 
-        for part in normalized_path.components().filter_map(|c| match c {
+        for part in rest.components().filter_map(|c| match c {
             Component::Normal(p) => Some(p),
             _ => None,
         }) {
             let part_str = part.to_string_lossy();
 
-            let found = current
+            let mut found: Vec<&Node> = current
                 .children
                 .values()
-                .find(|node| node.name() == part_str);
+                .filter(|node| node.name() == part_str)
+                .collect();
 
-            if let Some(next) = found {
-                current = next;
-            } else {
-                return Err(Error::Message(format!(
-                    "Path not found: {}",
-                    path.display()
-                )));
+            if found.is_empty() {
+                if let Some((name, prefix)) = part_str.rsplit_once('@') {
+                    let prefix = prefix.to_lowercase();
+                    found = current
+                        .children
+                        .values()
+                        .filter(|node| node.name() == name && node.id().starts_with(&prefix))
+                        .collect();
+                }
             }
+
+            current = match found.len() {
+                0 => {
+                    return Err(Error::Message(format!(
+                        "Path not found: {}",
+                        path.display()
+                    )))
+                }
+                1 => found[0],
+                _ if strict => {
+                    let mut candidates: Vec<String> = found
+                        .iter()
+                        .map(|node| format!("{}@{}", node.name(), node.short_id()))
+                        .collect();
+                    candidates.sort();
+                    return Err(Error::Message(format!(
+                        "Ambiguous path: {} matches {}",
+                        path.display(),
+                        candidates.join(", ")
+                    )));
+                }
+                _ => {
+                    log::warn!(
+                        "{} matches {} entries, using the most recently modified one",
+                        part_str,
+                        found.len()
+                    );
+                    found
+                        .into_iter()
+                        .max_by(|a, b| {
+                            a.document
+                                .last_modified
+                                .cmp(&b.document.last_modified)
+                                .then_with(|| a.id().cmp(&b.id()))
+                        })
+                        .unwrap()
+                }
+            };
         }
 
         Ok(current)
     }
+
+    /// Finds the node whose ID starts with `prefix`, which has to be unique.
+    pub fn find_node_by_id_prefix(&self, prefix: &str) -> Result<&Node, Error> {
+        let prefix = prefix.to_lowercase();
+        let mut matches = self
+            .docs
+            .iter()
+            .filter(|doc| doc.id.to_string().starts_with(&prefix));
+
+        let doc = match (matches.next(), matches.next()) {
+            (Some(doc), None) if !prefix.is_empty() => doc,
+            (None, _) => {
+                return Err(Error::Message(format!(
+                    "No document with ID prefix: {}",
+                    prefix
+                )))
+            }
+            _ => return Err(Error::Message(format!("Ambiguous ID prefix: {}", prefix))),
+        };
+
+        self.tree
+            .find_node(&doc.id.to_string())
+            .ok_or_else(|| Error::Message(format!("Document not in file tree: {}", doc.id)))
+    }
+
+    /// Splits a normalized path into the node it starts at and the remaining components.
+    ///
+    /// Paths start at the root unless their first component is an
+    /// `id:<prefix>` selector, in which case they start at that document.
+    /// An entry at the root whose name looks like a selector takes precedence.
+    /// `..` right after the first component moves up from that entry.
+    fn start_node<'a, 'p>(&'a self, path: &'p Path) -> Result<(&'a Node, &'p Path), Error> {
+        let mut components = path.components();
+        let rest = loop {
+            let rest = components.as_path();
+            match components.next() {
+                Some(Component::Normal(_)) => break rest,
+                Some(_) => continue,
+                None => return Ok((&self.tree.root, rest)),
+            }
+        };
+        let first = rest
+            .components()
+            .next()
+            .unwrap()
+            .as_os_str()
+            .to_string_lossy();
+        let Some(prefix) = first.strip_prefix(ID_SELECTOR) else {
+            return Ok((&self.tree.root, rest));
+        };
+
+        let literal = self
+            .tree
+            .root
+            .labeled_children()
+            .into_iter()
+            .find(|(label, _)| *label == first)
+            .map(|(_, node)| node);
+        let mut node = match literal {
+            Some(node) => node,
+            None => self.find_node_by_id_prefix(prefix)?,
+        };
+        loop {
+            let after = components.as_path();
+            match components.next() {
+                Some(Component::ParentDir) => node = self.parent_node(node),
+                _ => return Ok((node, after)),
+            }
+        }
+    }
+
+    /// Returns the folder containing `node`, or the root for top-level entries.
+    fn parent_node(&self, node: &Node) -> &Node {
+        match node.document.parent.as_str() {
+            "" => &self.tree.root,
+            "trash" => self
+                .tree
+                .root
+                .children
+                .get("trash")
+                .unwrap_or(&self.tree.root),
+            parent => self.tree.find_node(parent).unwrap_or(&self.tree.root),
+        }
+    }
+
+    /// Walks the tree below `root` and returns the full path of every entry matching `query`.
+    ///
+    /// The trash is only searched when `root` is inside it. Results are sorted by path.
+    pub fn find(&self, root: &Path, query: &FindQuery) -> Result<Vec<(PathBuf, &Node)>, Error> {
+        let pattern = query
+            .name
+            .as_deref()
+            .map(glob::Pattern::new)
+            .transpose()
+            .map_err(|e| Error::Message(format!("Invalid name pattern: {}", e)))?;
+
+        let root_path = normalize_path(root, Path::new("/"));
+        let root_node = self.find_node_by_path(&root_path)?;
+
+        let mut matches = Vec::new();
+        let mut stack = vec![(root_path, root_node)];
+        while let Some((path, node)) = stack.pop() {
+            for (label, child) in node.labeled_children() {
+                if child.id() == TRASH_ID && path == Path::new("/") {
+                    continue;
+                }
+                let child_path = path.join(label);
+                if query_matches(query, pattern.as_ref(), child) {
+                    matches.push((child_path.clone(), child));
+                }
+                if child.is_directory() {
+                    stack.push((child_path, child));
+                }
+            }
+        }
+
+        matches.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(matches)
+    }
+
+    /// Expands a remote glob pattern (`*`, `?`, `[...]`, `**`) into the matching paths.
+    ///
+    /// `pattern` has to be absolute. A pattern without wildcards is returned
+    /// as-is so the caller reports a missing path the usual way. Components
+    /// that exactly match an existing name are taken literally, and wildcards
+    /// never match the trash.
+    pub fn expand_glob(&self, pattern: &Path) -> Result<Vec<PathBuf>, Error> {
+        let pattern = normalize_path(pattern, Path::new("/"));
+        if !pattern.to_string_lossy().contains(['*', '?', '[']) {
+            return Ok(vec![pattern]);
+        }
+
+        let (start, rest) = self.start_node(&pattern)?;
+        let consumed = pattern.components().count() - rest.components().count();
+        let start_path: PathBuf = pattern.components().take(consumed).collect();
+        let mut candidates = vec![(start_path, start)];
+        for component in rest.components().filter_map(|c| match c {
+            Component::Normal(p) => Some(p.to_string_lossy()),
+            _ => None,
+        }) {
+            let mut next = Vec::new();
+            for (path, node) in &candidates {
+                if component == "**" {
+                    collect_descendants(path, node, &mut next);
+                    continue;
+                }
+                let children = node.labeled_children();
+                let literal: Vec<_> = children
+                    .iter()
+                    .filter(|(label, child)| child.name() == component || *label == component)
+                    .collect();
+                if !literal.is_empty() {
+                    next.extend(
+                        literal
+                            .into_iter()
+                            .map(|(label, child)| (path.join(label), *child)),
+                    );
+                    continue;
+                }
+                let glob = glob::Pattern::new(&component)
+                    .map_err(|e| Error::Message(format!("Invalid pattern: {}", e)))?;
+                for (label, child) in children {
+                    if child.id() != TRASH_ID && glob.matches(child.name()) {
+                        next.push((path.join(label), child));
+                    }
+                }
+            }
+            candidates = next;
+        }
+
+        let mut matches: Vec<PathBuf> = candidates.into_iter().map(|(path, _)| path).collect();
+        matches.sort();
+        matches.dedup();
+        if matches.is_empty() {
+            return Err(Error::Message(format!(
+                "No matches for pattern: {}",
+                pattern.display()
+            )));
+        }
+        Ok(matches)
+    }
 }
 
+/// Adds `node` and everything below it (except the trash) to `out`, as `**` does.
+fn collect_descendants<'a>(path: &Path, node: &'a Node, out: &mut Vec<(PathBuf, &'a Node)>) {
+    out.push((path.to_path_buf(), node));
+    for (label, child) in node.labeled_children() {
+        if child.id() != TRASH_ID {
+            collect_descendants(&path.join(label), child, out);
+        }
+    }
+}
+
+fn query_matches(query: &FindQuery, pattern: Option<&glob::Pattern>, node: &Node) -> bool {
+    let doc = &node.document;
+    pattern.is_none_or(|p| p.matches(node.name()))
+        && query.kind.is_none_or(|kind| node.kind() == kind)
+        && query.modified_after.is_none_or(|t| doc.last_modified > t)
+        && query.modified_before.is_none_or(|t| doc.last_modified < t)
+        && (!query.pinned || doc.bookmarked)
+        && query.tags.iter().all(|tag| doc.tags.contains(tag))
+}
+
+/// Resolves `.` and `..` in `path` relative to `cwd` and makes it absolute.
+///
+/// An `id:<prefix>` selector is only recognised as the first component, where
+/// it replaces `cwd`. Since the location of the selected entry is unknown
+/// here, `..` after it is kept for the file system to resolve.
 pub fn normalize_path(path: &Path, cwd: &Path) -> PathBuf {
     let mut components: Vec<Component> = Vec::new();
 
-    if path.is_relative() {
+    let first = path
+        .components()
+        .find(|comp| !matches!(comp, Component::RootDir | Component::CurDir));
+    let selected = matches!(first, Some(Component::Normal(p)) if is_id_selector(p));
+    if selected {
+        components.push(Component::RootDir);
+    } else if path.is_relative() {
         for comp in cwd.components() {
             components.push(comp);
         }
@@ -163,14 +483,23 @@ pub fn normalize_path(path: &Path, cwd: &Path) -> PathBuf {
         match comp {
             Component::CurDir => {} // This is "." - do nothing
             Component::ParentDir => {
-                // This is ".." - pop the last part if possible
-                // We check to avoid popping the RootDir or Prefix
-                if let Some(last) = components.last() {
-                    if matches!(last, Component::Normal(_)) {
-                        components.pop();
+                // An ID selector or `..` following it can't be resolved here
+                let unresolved = match components.as_slice() {
+                    [Component::RootDir, Component::Normal(first), rest @ ..] => {
+                        is_id_selector(first)
+                            && rest.iter().all(|c| matches!(c, Component::ParentDir))
                     }
+                    _ => false,
+                };
+                if unresolved {
+                    components.push(comp);
+                } else if let Some(Component::Normal(_)) = components.last() {
+                    // This is ".." - pop the last part if possible
+                    // We check to avoid popping the RootDir or Prefix
+                    components.pop();
                 }
             }
+            Component::RootDir if selected => {}
             _ => components.push(comp),
         }
     }
@@ -178,6 +507,29 @@ pub fn normalize_path(path: &Path, cwd: &Path) -> PathBuf {
     components.iter().collect()
 }
 
+fn is_id_selector(component: &std::ffi::OsStr) -> bool {
+    component.to_string_lossy().starts_with(ID_SELECTOR)
+}
+
+/// Turns a visible name into something that can safely be used as a single
+/// local path component.
+pub fn sanitize_file_name(name: &str) -> String {
+    let sanitized: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let sanitized = sanitized.trim();
+
+    match sanitized {
+        "" | "." | ".." => "_".to_string(),
+        _ => sanitized.to_string(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -225,4 +577,256 @@ mod tests {
             PathBuf::from("/foo/bar")
         );
     }
+
+    #[test]
+    fn test_sanitize_file_name() {
+        assert_eq!(sanitize_file_name("Notes"), "Notes");
+        assert_eq!(sanitize_file_name("a/b\\c"), "a_b_c");
+        assert_eq!(sanitize_file_name("  padded  "), "padded");
+        assert_eq!(sanitize_file_name("tab\there"), "tab_here");
+        assert_eq!(sanitize_file_name(".."), "_");
+        assert_eq!(sanitize_file_name(""), "_");
+    }
+
+    fn test_filesystem() -> FileSystem {
+        let folder = uuid::Uuid::from_u128(1);
+        let docs = vec![
+            Document {
+                id: folder,
+                display_name: "Papers".to_string(),
+                doc_type: crate::objects::DocumentType::Collection,
+                ..Default::default()
+            },
+            Document {
+                id: uuid::Uuid::from_u128(2),
+                display_name: "2025-attention.pdf".to_string(),
+                parent: folder.to_string(),
+                bookmarked: true,
+                tags: vec!["ml".to_string()],
+                ..Default::default()
+            },
+            Document {
+                id: uuid::Uuid::from_u128(3),
+                display_name: "2024-notes".to_string(),
+                parent: folder.to_string(),
+                ..Default::default()
+            },
+            Document {
+                id: uuid::Uuid::from_u128(4),
+                display_name: "2025-old".to_string(),
+                parent: "trash".to_string(),
+                ..Default::default()
+            },
+        ];
+        FileSystem {
+            tree: FileTree::build(docs.clone()),
+            docs,
+            ..FileSystem::new()
+        }
+    }
+
+    #[test]
+    fn test_complete_path() {
+        let fs = test_filesystem();
+        assert_eq!(fs.complete_path("Pa", Path::new("/")), vec!["Papers/"]);
+        assert_eq!(
+            fs.complete_path("Papers/2025", Path::new("/")),
+            vec!["Papers/2025-attention.pdf"]
+        );
+        assert_eq!(
+            fs.complete_path("../Papers/2024", Path::new("/Papers")),
+            vec!["../Papers/2024-notes"]
+        );
+        assert_eq!(
+            fs.complete_path("", Path::new("/Papers")),
+            vec!["2024-notes", "2025-attention.pdf"]
+        );
+        assert!(fs.complete_path("Missing/", Path::new("/")).is_empty());
+    }
+
+    #[test]
+    fn test_find() {
+        let fs = test_filesystem();
+        let paths = |query: &FindQuery| -> Vec<PathBuf> {
+            fs.find(Path::new("/"), query)
+                .unwrap()
+                .into_iter()
+                .map(|(p, _)| p)
+                .collect()
+        };
+
+        let by_name = FindQuery {
+            name: Some("2025-*".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            paths(&by_name),
+            vec![PathBuf::from("/Papers/2025-attention.pdf")]
+        );
+
+        let dirs = FindQuery {
+            kind: Some(NodeKind::Directory),
+            ..Default::default()
+        };
+        assert_eq!(paths(&dirs), vec![PathBuf::from("/Papers")]);
+
+        let pinned_ml = FindQuery {
+            pinned: true,
+            tags: vec!["ml".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            paths(&pinned_ml),
+            vec![PathBuf::from("/Papers/2025-attention.pdf")]
+        );
+
+        let in_trash = fs.find(Path::new("/trash"), &by_name).unwrap();
+        assert_eq!(in_trash.len(), 1);
+        assert_eq!(in_trash[0].0, PathBuf::from("/trash/2025-old"));
+    }
+
+    #[test]
+    fn test_expand_glob() {
+        let fs = test_filesystem();
+        let expand = |pattern: &str| fs.expand_glob(Path::new(pattern)).unwrap();
+
+        assert_eq!(
+            expand("/Papers/2025-*"),
+            vec![PathBuf::from("/Papers/2025-attention.pdf")]
+        );
+        assert_eq!(
+            expand("/Papers/202[45]-*"),
+            vec![
+                PathBuf::from("/Papers/2024-notes"),
+                PathBuf::from("/Papers/2025-attention.pdf")
+            ]
+        );
+        assert_eq!(
+            expand("/**/*.pdf"),
+            vec![PathBuf::from("/Papers/2025-attention.pdf")]
+        );
+        // Wildcards do not reach into the trash
+        assert_eq!(expand("/*"), vec![PathBuf::from("/Papers")]);
+        // Without wildcards the path is passed through untouched
+        assert_eq!(expand("/Missing"), vec![PathBuf::from("/Missing")]);
+        assert!(fs.expand_glob(Path::new("/Papers/*.epub")).is_err());
+    }
+
+    #[test]
+    fn test_duplicate_names() {
+        let older = Document {
+            id: uuid::Uuid::parse_str("aaaaaaaa-0000-0000-0000-000000000000").unwrap(),
+            display_name: "Notes".to_string(),
+            ..Default::default()
+        };
+        let newer = Document {
+            id: uuid::Uuid::parse_str("bbbbbbbb-0000-0000-0000-000000000000").unwrap(),
+            display_name: "Notes".to_string(),
+            last_modified: Utc::now(),
+            ..Default::default()
+        };
+        let docs = vec![older.clone(), newer.clone()];
+        let fs = FileSystem {
+            tree: FileTree::build(docs.clone()),
+            docs,
+            ..FileSystem::new()
+        };
+
+        let lenient = fs.find_node_by_path(Path::new("/Notes")).unwrap();
+        assert_eq!(lenient.document.id, newer.id);
+        assert!(fs.find_node_by_path_strict(Path::new("/Notes")).is_err());
+
+        let selected = fs
+            .find_node_by_path_strict(Path::new("/Notes@aaaa"))
+            .unwrap();
+        assert_eq!(selected.document.id, older.id);
+
+        assert_eq!(
+            fs.expand_glob(Path::new("/N*")).unwrap(),
+            vec![
+                PathBuf::from("/Notes@aaaaaaaa"),
+                PathBuf::from("/Notes@bbbbbbbb")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_id_selector() {
+        let fs = test_filesystem();
+        let folder_id = uuid::Uuid::from_u128(1).to_string();
+
+        assert_eq!(
+            normalize_path(Path::new("id:0000/sub"), Path::new("/foo")),
+            PathBuf::from("/id:0000/sub")
+        );
+
+        let prefix = &folder_id[..folder_id.len() - 4];
+        let folder = fs
+            .find_node_by_path(Path::new(&format!("id:{}", folder_id)))
+            .unwrap();
+        assert_eq!(folder.name(), "Papers");
+        let child = fs
+            .find_node_by_path(Path::new(&format!("/id:{}/2024-notes", folder_id)))
+            .unwrap();
+        assert_eq!(child.document.id, uuid::Uuid::from_u128(3));
+        // All test IDs share their leading digits
+        assert!(fs
+            .find_node_by_path(Path::new(&format!("id:{}", prefix)))
+            .is_err());
+
+        assert_eq!(
+            fs.expand_glob(Path::new(&format!("/id:{}/2025-*", folder_id)))
+                .unwrap(),
+            vec![PathBuf::from(format!(
+                "/id:{}/2025-attention.pdf",
+                folder_id
+            ))]
+        );
+    }
+
+    #[test]
+    fn test_id_selector_paths() {
+        assert_eq!(
+            normalize_path(Path::new("/foo/id:bar/baz"), Path::new("/")),
+            PathBuf::from("/foo/id:bar/baz")
+        );
+        assert_eq!(
+            normalize_path(Path::new("id:00/sub/../.."), Path::new("/foo")),
+            PathBuf::from("/id:00/..")
+        );
+
+        let mut docs = test_filesystem().docs;
+        docs.push(Document {
+            id: uuid::Uuid::from_u128(5),
+            display_name: "id:ideas".to_string(),
+            doc_type: crate::objects::DocumentType::Collection,
+            parent: uuid::Uuid::from_u128(1).to_string(),
+            ..Default::default()
+        });
+        docs.push(Document {
+            id: uuid::Uuid::from_u128(6),
+            display_name: "id:top".to_string(),
+            ..Default::default()
+        });
+        let fs = FileSystem {
+            tree: FileTree::build(docs.clone()),
+            docs,
+            ..FileSystem::new()
+        };
+
+        let nested = fs.find_node_by_path(Path::new("/Papers/id:ideas")).unwrap();
+        assert_eq!(nested.document.id, uuid::Uuid::from_u128(5));
+        let top = fs.find_node_by_path(Path::new("/id:top")).unwrap();
+        assert_eq!(top.document.id, uuid::Uuid::from_u128(6));
+
+        let doc_id = uuid::Uuid::from_u128(3).to_string();
+        let sibling = fs
+            .find_node_by_path(Path::new(&format!("id:{}/../2025-attention.pdf", doc_id)))
+            .unwrap();
+        assert_eq!(sibling.document.id, uuid::Uuid::from_u128(2));
+        let root = fs
+            .find_node_by_path(Path::new(&format!("id:{}/../..", doc_id)))
+            .unwrap();
+        assert_eq!(root.id(), fs.tree.root.id());
+    }
 }
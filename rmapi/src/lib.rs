@@ -1,9 +1,18 @@
+pub mod backup;
+pub mod cache;
+pub mod changes;
 pub mod client;
 pub mod constants;
 pub mod endpoints;
+pub mod epub;
 pub mod error;
 pub mod filesystem;
 pub mod objects;
+pub mod progress;
+pub mod search;
+pub mod text;
+pub mod upload;
+pub mod usage;
 
 /// Re-exports the `RmClient` struct from the `client` module.
 pub use client::RmClient;
@@ -38,6 +38,16 @@ pub struct Document {
     pub bookmarked: bool,
     #[serde(rename = "Parent")]
     pub parent: String,
+    #[serde(rename = "Tags", default)]
+    pub tags: Vec<String>,
+    /// Total size in bytes of all files of the document, as listed in the root index
+    #[serde(rename = "Size", default)]
+    pub size: u64,
+    #[serde(rename = "CreatedTime", default)]
+    pub created: DateTime<Utc>,
+    /// Number of pages, 0 for folders and documents that do not list any
+    #[serde(rename = "PageCount", default)]
+    pub page_count: u32,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
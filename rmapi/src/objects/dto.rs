@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -28,6 +29,24 @@ pub struct V4Metadata {
     pub other: std::collections::HashMap<String, serde_json::Value>,
 }
 
+impl V4Metadata {
+    pub fn created(&self) -> Option<DateTime<Utc>> {
+        timestamp_from_millis(&self.created_time)
+    }
+
+    pub fn modified(&self) -> Option<DateTime<Utc>> {
+        timestamp_from_millis(&self.last_modified)
+    }
+}
+
+/// Parses the millisecond timestamps used in `.metadata` files.
+fn timestamp_from_millis(value: &str) -> Option<DateTime<Utc>> {
+    value
+        .parse::<i64>()
+        .ok()
+        .and_then(DateTime::from_timestamp_millis)
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct V4Content {
     #[serde(rename = "extraMetadata", default)]
@@ -54,6 +73,49 @@ pub struct V4Content {
     pub transform: std::collections::HashMap<String, f32>,
 }
 
+impl V4Content {
+    /// Extracts tag names from raw `.content` JSON.
+    ///
+    /// Older documents store tags as plain strings while newer ones use
+    /// `{"name": ..., "timestamp": ...}` objects, so this does not go through
+    /// the typed `tags` field.
+    pub fn tags_from_json(data: &[u8]) -> Vec<String> {
+        let Ok(value) = serde_json::from_slice::<serde_json::Value>(data) else {
+            return Vec::new();
+        };
+        value
+            .get("tags")
+            .and_then(|tags| tags.as_array())
+            .map(|tags| {
+                tags.iter()
+                    .filter_map(|tag| match tag {
+                        serde_json::Value::String(name) => Some(name.clone()),
+                        serde_json::Value::Object(obj) => {
+                            obj.get("name").and_then(|n| n.as_str()).map(str::to_string)
+                        }
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Reads the page count from raw `.content` JSON.
+    ///
+    /// Falls back to counting the listed pages when `pageCount` is missing,
+    /// and returns `None` when the file lists no pages at all, as for folders.
+    pub fn page_count_from_json(data: &[u8]) -> Option<u32> {
+        let value = serde_json::from_slice::<serde_json::Value>(data).ok()?;
+        match value.get("pageCount").and_then(|c| c.as_u64()) {
+            Some(count) => Some(count as u32),
+            None => {
+                let pages = crate::text::page_order(data).len() as u32;
+                (pages > 0).then_some(pages)
+            }
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ExtraMetadata {
     #[serde(rename = "LastBrushColor", default)]
@@ -0,0 +1,30 @@
+use crate::objects::IndexEntry;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Everything the cloud stores about a single document or folder.
+#[derive(Debug, Clone, Serialize)]
+pub struct DocumentInfo {
+    pub id: String,
+    pub name: String,
+    /// Parent ID as stored in the metadata ("" for the root, "trash" for deleted items)
+    pub parent: String,
+    /// `DocumentType` or `CollectionType`
+    #[serde(rename = "type")]
+    pub doc_type: String,
+    pub version: u64,
+    pub created: Option<DateTime<Utc>>,
+    pub last_modified: Option<DateTime<Utc>>,
+    pub pinned: bool,
+    /// `pdf`, `epub` or `notebook`; absent for folders
+    pub file_type: Option<String>,
+    /// Absent for folders
+    pub page_count: Option<u32>,
+    pub tags: Vec<String>,
+    /// Hash of the docSchema
+    pub hash: String,
+    pub size: u64,
+    pub subfiles: Vec<IndexEntry>,
+    /// The `.metadata` file as stored
+    pub metadata: serde_json::Value,
+}
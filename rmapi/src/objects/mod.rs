@@ -2,6 +2,7 @@ mod collection;
 mod document;
 mod dto;
 mod entry;
+mod info;
 mod node;
 
 pub use collection::Collection;
@@ -10,4 +11,5 @@ pub use dto::{
     ClientRegistration, ExtraMetadata, RootInfo, StorageInfo, V4Content, V4Entry, V4Metadata,
 };
 pub use entry::IndexEntry;
-pub use node::{FileTree, Node};
+pub use info::DocumentInfo;
+pub use node::{FileTree, Node, NodeKind};
@@ -2,6 +2,16 @@ use crate::objects::{Document, DocumentType};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+/// Number of ID characters used to tell same-named siblings apart.
+pub const SHORT_ID_LEN: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeKind {
+    Directory,
+    File,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Node {
     pub document: Document,
@@ -20,6 +30,14 @@ impl Node {
         self.document.doc_type == DocumentType::Collection
     }
 
+    pub fn kind(&self) -> NodeKind {
+        if self.is_directory() {
+            NodeKind::Directory
+        } else {
+            NodeKind::File
+        }
+    }
+
     pub fn id(&self) -> String {
         self.document.id.to_string()
     }
@@ -27,8 +45,67 @@ impl Node {
     pub fn name(&self) -> &str {
         &self.document.display_name
     }
+
+    pub fn short_id(&self) -> String {
+        self.id().chars().take(SHORT_ID_LEN).collect()
+    }
+
+    /// Returns every child with the name it can be addressed by in a path.
+    ///
+    /// That is the plain name, or `name@<short id>` when several children
+    /// share the same name.
+    pub fn labeled_children(&self) -> Vec<(String, &Node)> {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for child in self.children.values() {
+            *counts.entry(child.name()).or_default() += 1;
+        }
+
+        self.children
+            .values()
+            .map(|child| {
+                let label = if counts[child.name()] > 1 {
+                    format!("{}@{}", child.name(), child.short_id())
+                } else {
+                    child.name().to_string()
+                };
+                (label, child)
+            })
+            .collect()
+    }
+
+    /// Labeled children in display order: directories first, then files,
+    /// both sorted case-insensitively.
+    pub fn listing(&self) -> Vec<(String, &Node)> {
+        let mut entries = self.labeled_children();
+        entries.sort_by(|(a_label, a), (b_label, b)| {
+            b.is_directory()
+                .cmp(&a.is_directory())
+                .then_with(|| a_label.to_lowercase().cmp(&b_label.to_lowercase()))
+        });
+        entries
+    }
+
+    /// Size of this node and everything below it.
+    pub fn total_size(&self) -> u64 {
+        self.document.size + self.children.values().map(Node::total_size).sum::<u64>()
+    }
+
+    /// Number of documents (not folders) below this node.
+    pub fn document_count(&self) -> usize {
+        self.children
+            .values()
+            .map(|child| {
+                if child.is_directory() {
+                    child.document_count()
+                } else {
+                    1
+                }
+            })
+            .sum()
+    }
 }
 
+#[derive(Clone)]
 pub struct FileTree {
     pub root: Node,
 }
@@ -70,6 +147,11 @@ impl FileTree {
         self.root.children.insert(trash_id.to_string(), trash_node);
     }
 
+    /// Looks up a node anywhere in the tree by its full ID.
+    pub fn find_node(&self, id: &str) -> Option<&Node> {
+        find_node(&self.root, id)
+    }
+
     pub fn build(documents: Vec<Document>) -> Self {
         let mut tree = Self::new();
         tree.add_trash_node();
@@ -134,6 +216,16 @@ impl FileTree {
     }
 }
 
+fn find_node<'a>(current: &'a Node, id: &str) -> Option<&'a Node> {
+    if current.id() == id {
+        return Some(current);
+    }
+    current
+        .children
+        .values()
+        .find_map(|child| find_node(child, id))
+}
+
 fn find_node_mut<'a>(current: &'a mut Node, id: &str) -> Option<&'a mut Node> {
     if current.id() == id {
         return Some(current);
@@ -145,3 +237,42 @@ fn find_node_mut<'a>(current: &'a mut Node, id: &str) -> Option<&'a mut Node> {
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_aggregates_and_listing() {
+        let folder = Uuid::from_u128(1);
+        let tree = FileTree::build(vec![
+            Document {
+                id: folder,
+                display_name: "b-folder".to_string(),
+                doc_type: DocumentType::Collection,
+                size: 10,
+                ..Default::default()
+            },
+            Document {
+                id: Uuid::from_u128(2),
+                display_name: "inner".to_string(),
+                parent: folder.to_string(),
+                size: 100,
+                ..Default::default()
+            },
+            Document {
+                id: Uuid::from_u128(3),
+                display_name: "A-doc".to_string(),
+                size: 1000,
+                ..Default::default()
+            },
+        ]);
+
+        assert_eq!(tree.root.total_size(), 1110);
+        assert_eq!(tree.root.document_count(), 2);
+
+        let labels: Vec<String> = tree.root.listing().into_iter().map(|(l, _)| l).collect();
+        assert_eq!(labels, vec!["b-folder", "trash", "A-doc"]);
+    }
+}
@@ -0,0 +1,24 @@
+/// Receives progress of uploads and downloads.
+///
+/// Set it with [`crate::RmClient::set_progress`]. Documents are transferred
+/// concurrently, so every method can be called from several tasks at once.
+pub trait Progress: Send + Sync {
+    /// A transfer of `documents` documents with `bytes` bytes of file data
+    /// begins. `bytes` is an estimate for downloads of notebooks.
+    fn start(&self, _documents: usize, _bytes: u64) {}
+
+    /// `bytes` more bytes of file data were sent or received.
+    fn advance(&self, _bytes: u64) {}
+
+    /// The document `name` was transferred or skipped.
+    fn document_done(&self, _name: &str) {}
+
+    /// The transfer ended, successfully or not.
+    fn finish(&self) {}
+}
+
+/// Ignores all progress, the default of every client.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoProgress;
+
+impl Progress for NoProgress {}
@@ -0,0 +1,139 @@
+use crate::error::Error;
+use crate::text::PageText;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+
+/// Extracted text of a document, tagged with the docSchema hash it came from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedDocument {
+    pub hash: String,
+    pub pages: Vec<PageText>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchMatch {
+    pub doc_id: String,
+    pub page: u32,
+    pub line: String,
+}
+
+/// Compiles a search pattern, so it can be validated before any work is done.
+pub fn compile_pattern(pattern: &str, ignore_case: bool) -> Result<Regex, Error> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|e| Error::Message(format!("Invalid pattern: {}", e)))
+}
+
+/// Local full-text index, kept in the cache directory.
+///
+/// A document only has to be re-extracted when its docSchema hash changes.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SearchIndex {
+    documents: HashMap<String, IndexedDocument>,
+}
+
+impl SearchIndex {
+    pub fn load() -> Result<Self, Error> {
+        let path = Self::index_path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let data = fs::read_to_string(path)?;
+        // A corrupt index is only a cache; start over instead of failing
+        Ok(serde_json::from_str(&data).unwrap_or_default())
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        let path = Self::index_path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Whether the stored text of `doc_id` was extracted from `hash`.
+    pub fn is_fresh(&self, doc_id: &str, hash: &str) -> bool {
+        self.documents
+            .get(doc_id)
+            .is_some_and(|doc| doc.hash == hash)
+    }
+
+    pub fn insert(&mut self, doc_id: String, document: IndexedDocument) {
+        self.documents.insert(doc_id, document);
+    }
+
+    /// Drops documents that no longer exist.
+    pub fn retain_ids(&mut self, ids: &HashSet<&str>) {
+        self.documents.retain(|id, _| ids.contains(id.as_str()));
+    }
+
+    /// Searches the given documents line by line, in the order of `doc_ids`.
+    pub fn search(&self, doc_ids: &[String], regex: &Regex) -> Vec<SearchMatch> {
+        let mut matches = Vec::new();
+        for doc_id in doc_ids {
+            let Some(doc) = self.documents.get(doc_id) else {
+                continue;
+            };
+            for page in &doc.pages {
+                for line in page.text.lines().filter(|line| regex.is_match(line)) {
+                    matches.push(SearchMatch {
+                        doc_id: doc_id.clone(),
+                        page: page.page,
+                        line: line.trim().to_string(),
+                    });
+                }
+            }
+        }
+        matches
+    }
+
+    fn index_path() -> Result<PathBuf, Error> {
+        Ok(dirs::cache_dir()
+            .ok_or_else(|| Error::Message("Could not find cache directory".to_string()))?
+            .join("rmapi/search.index"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search() {
+        let mut index = SearchIndex::default();
+        index.insert(
+            "doc".to_string(),
+            IndexedDocument {
+                hash: "abc".to_string(),
+                pages: vec![
+                    PageText {
+                        page: 1,
+                        text: "Attention is all\nyou need".to_string(),
+                    },
+                    PageText {
+                        page: 2,
+                        text: "Self-attention layers".to_string(),
+                    },
+                ],
+            },
+        );
+
+        assert!(index.is_fresh("doc", "abc"));
+        assert!(!index.is_fresh("doc", "def"));
+
+        let ids = vec!["doc".to_string()];
+        let matches = index.search(&ids, &compile_pattern("attention", true).unwrap());
+        assert_eq!(
+            matches.iter().map(|m| m.page).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        let regex = compile_pattern("attention", false).unwrap();
+        assert_eq!(index.search(&ids, &regex).len(), 1);
+        assert!(compile_pattern("(", false).is_err());
+    }
+}
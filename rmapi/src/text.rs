@@ -0,0 +1,368 @@
+use crate::error::Error;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::sync::LazyLock;
+
+/// Searchable text of a single page (or EPUB chapter), numbered from 1.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PageText {
+    pub page: u32,
+    pub text: String,
+}
+
+static TAG_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)<[^>]*>").unwrap());
+static SCRIPT_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<(script|style)[^>]*>.*?</(script|style)>").unwrap());
+static ENTITY_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"&(#[0-9]+|#x[0-9a-fA-F]+|[a-zA-Z]+);").unwrap());
+static ATTR_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"([a-zA-Z:-]+)\s*=\s*"([^"]*)""#).unwrap());
+static ITEM_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<(?:\w+:)?item\s[^>]*>").unwrap());
+static ITEMREF_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<(?:\w+:)?itemref\s[^>]*>").unwrap());
+
+const RM_V6_HEADER: &[u8] = b"reMarkable .lines file, version=6";
+/// The header is padded with spaces to a fixed length
+const RM_V6_HEADER_LEN: usize = 43;
+const RM_BLOCK_GLYPH_ITEM: u8 = 0x03;
+const RM_BLOCK_ROOT_TEXT: u8 = 0x07;
+
+/// Extracts the text layer of every page of a PDF.
+pub fn extract_pdf(data: &[u8]) -> Result<Vec<PageText>, Error> {
+    let doc = lopdf::Document::load_mem(data)
+        .map_err(|e| Error::Message(format!("Failed to parse PDF: {}", e)))?;
+
+    Ok(doc
+        .get_pages()
+        .keys()
+        .filter_map(|&page| {
+            let text = doc.extract_text(&[page]).ok()?;
+            Some(PageText { page, text })
+        })
+        .collect())
+}
+
+/// Extracts the text of every spine item of an EPUB, treating each as a page.
+pub fn extract_epub(data: &[u8]) -> Result<Vec<PageText>, Error> {
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(data))
+        .map_err(|e| Error::Message(format!("Failed to open EPUB: {}", e)))?;
+
+    let container = read_zip_entry(&mut archive, "META-INF/container.xml")?;
+    let opf_path = attributes(&container)
+        .into_iter()
+        .find(|(name, _)| name == "full-path")
+        .map(|(_, value)| value)
+        .ok_or_else(|| Error::Message("EPUB container has no rootfile".to_string()))?;
+    let opf = read_zip_entry(&mut archive, &opf_path)?;
+    let base_dir = match opf_path.rfind('/') {
+        Some(idx) => &opf_path[..=idx],
+        None => "",
+    };
+
+    let manifest: Vec<(String, String)> = ITEM_RE
+        .find_iter(&opf)
+        .filter_map(|item| {
+            let attrs = attributes(item.as_str());
+            let id = attrs.iter().find(|(n, _)| n == "id")?.1.clone();
+            let href = attrs.iter().find(|(n, _)| n == "href")?.1.clone();
+            Some((id, href))
+        })
+        .collect();
+
+    let mut pages = Vec::new();
+    for itemref in ITEMREF_RE.find_iter(&opf) {
+        let attrs = attributes(itemref.as_str());
+        let Some((_, idref)) = attrs.iter().find(|(n, _)| n == "idref") else {
+            continue;
+        };
+        let Some((_, href)) = manifest.iter().find(|(id, _)| id == idref) else {
+            continue;
+        };
+        let path = format!("{}{}", base_dir, decode_entities(href));
+        if let Ok(html) = read_zip_entry(&mut archive, &path) {
+            pages.push(PageText {
+                page: pages.len() as u32 + 1,
+                text: html_to_text(&html),
+            });
+        }
+    }
+    Ok(pages)
+}
+
+/// Extracts typed text and highlighted text from a v6 `.rm` page.
+///
+/// This does not resolve the CRDT ordering of text items; it returns the
+/// strings in the order they are stored, which matches the reading order for
+/// text that was typed front to back.
+pub fn extract_rm(data: &[u8]) -> String {
+    if !data.starts_with(RM_V6_HEADER) {
+        return String::new();
+    }
+
+    let mut text = String::new();
+    let mut pos = RM_V6_HEADER_LEN;
+    while pos + 8 <= data.len() {
+        let length = u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+        let block_type = data[pos + 7];
+        let start = pos + 8;
+        let end = (start + length).min(data.len());
+        match block_type {
+            // Typed text is split into many small items that belong together
+            RM_BLOCK_ROOT_TEXT => text.push_str(&scan_tagged_strings(&data[start..end]).concat()),
+            // Every glyph item is a separate highlight
+            RM_BLOCK_GLYPH_ITEM => {
+                for highlight in scan_tagged_strings(&data[start..end]) {
+                    text.push('\n');
+                    text.push_str(&highlight);
+                }
+            }
+            _ => {}
+        }
+        pos = start + length;
+    }
+    text
+}
+
+/// Returns the page IDs of a document in reading order, as listed in its `.content`.
+pub fn page_order(content: &[u8]) -> Vec<String> {
+    let Ok(value) = serde_json::from_slice::<serde_json::Value>(content) else {
+        return Vec::new();
+    };
+
+    // Newer documents use `cPages`, older ones a flat `pages` list
+    if let Some(pages) = value.pointer("/cPages/pages").and_then(|p| p.as_array()) {
+        return pages
+            .iter()
+            .filter(|page| page.get("deleted").is_none())
+            .filter_map(|page| page.get("id").and_then(|id| id.as_str()))
+            .map(str::to_string)
+            .collect();
+    }
+    value
+        .get("pages")
+        .and_then(|p| p.as_array())
+        .map(|pages| {
+            pages
+                .iter()
+                .filter_map(|id| id.as_str())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Extracts the highlighted text from a legacy `.highlights/<page>.json` file.
+pub fn extract_highlights(data: &[u8]) -> String {
+    let Ok(value) = serde_json::from_slice::<serde_json::Value>(data) else {
+        return String::new();
+    };
+    let mut texts = Vec::new();
+    collect_text_fields(&value, &mut texts);
+    texts.join("\n")
+}
+
+/// Strips tags, scripts and styles from HTML and decodes common entities.
+pub fn html_to_text(html: &str) -> String {
+    let without_scripts = SCRIPT_RE.replace_all(html, " ");
+    let without_tags = TAG_RE.replace_all(&without_scripts, " ");
+    let decoded = decode_entities(&without_tags);
+    decoded
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Decodes numeric and the common named character references. Unknown
+/// names are left as they are.
+pub(crate) fn decode_entities(text: &str) -> String {
+    ENTITY_RE
+        .replace_all(text, |caps: &regex::Captures| {
+            let entity = &caps[1];
+            let decoded = if let Some(hex) = entity.strip_prefix("#x") {
+                u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+            } else if let Some(dec) = entity.strip_prefix('#') {
+                dec.parse().ok().and_then(char::from_u32)
+            } else {
+                match entity {
+                    "amp" => Some('&'),
+                    "lt" => Some('<'),
+                    "gt" => Some('>'),
+                    "quot" => Some('"'),
+                    "apos" => Some('\''),
+                    "nbsp" => Some(' '),
+                    "shy" => Some('\u{ad}'),
+                    "copy" => Some('©'),
+                    "reg" => Some('®'),
+                    "trade" => Some('™'),
+                    "deg" => Some('°'),
+                    "sect" => Some('§'),
+                    "para" => Some('¶'),
+                    "middot" => Some('·'),
+                    "bull" => Some('•'),
+                    "hellip" => Some('…'),
+                    "ndash" => Some('–'),
+                    "mdash" => Some('—'),
+                    "lsquo" => Some('‘'),
+                    "rsquo" => Some('’'),
+                    "sbquo" => Some('‚'),
+                    "ldquo" => Some('“'),
+                    "rdquo" => Some('”'),
+                    "bdquo" => Some('„'),
+                    "laquo" => Some('«'),
+                    "raquo" => Some('»'),
+                    "times" => Some('×'),
+                    "divide" => Some('÷'),
+                    "plusmn" => Some('±'),
+                    "euro" => Some('€'),
+                    "pound" => Some('£'),
+                    "yen" => Some('¥'),
+                    "cent" => Some('¢'),
+                    "auml" => Some('ä'),
+                    "ouml" => Some('ö'),
+                    "uuml" => Some('ü'),
+                    "Auml" => Some('Ä'),
+                    "Ouml" => Some('Ö'),
+                    "Uuml" => Some('Ü'),
+                    "szlig" => Some('ß'),
+                    "eacute" => Some('é'),
+                    "egrave" => Some('è'),
+                    "aacute" => Some('á'),
+                    "agrave" => Some('à'),
+                    "ccedil" => Some('ç'),
+                    _ => None,
+                }
+            };
+            decoded
+                .map(String::from)
+                .unwrap_or_else(|| caps[0].to_string())
+        })
+        .into_owned()
+}
+
+fn attributes(tag: &str) -> Vec<(String, String)> {
+    ATTR_RE
+        .captures_iter(tag)
+        .map(|caps| (caps[1].to_string(), caps[2].to_string()))
+        .collect()
+}
+
+fn read_zip_entry<R: std::io::Read + std::io::Seek>(
+    archive: &mut zip::ZipArchive<R>,
+    name: &str,
+) -> Result<String, Error> {
+    let mut entry = archive
+        .by_name(name)
+        .map_err(|e| Error::Message(format!("Missing {} in archive: {}", name, e)))?;
+    let mut content = String::new();
+    entry.read_to_string(&mut content)?;
+    Ok(content)
+}
+
+fn collect_text_fields(value: &serde_json::Value, texts: &mut Vec<String>) {
+    match value {
+        serde_json::Value::Object(obj) => {
+            for (key, value) in obj {
+                match value {
+                    serde_json::Value::String(text) if key == "text" => texts.push(text.clone()),
+                    _ => collect_text_fields(value, texts),
+                }
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for item in items {
+                collect_text_fields(item, texts);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Finds strings stored as tagged length-prefixed values inside an `.rm` block.
+///
+/// A string is a `Length4` tag, a u32 length, a varuint string length, an
+/// `is_ascii` flag and the bytes. The two lengths have to agree, which makes
+/// false positives in binary data unlikely.
+fn scan_tagged_strings(block: &[u8]) -> Vec<String> {
+    const TAG_TYPE_LENGTH4: u8 = 0x0C;
+
+    let mut strings = Vec::new();
+    let mut i = 0;
+    while i + 6 <= block.len() {
+        if block[i] & 0x0F != TAG_TYPE_LENGTH4 {
+            i += 1;
+            continue;
+        }
+        let length = u32::from_le_bytes(block[i + 1..i + 5].try_into().unwrap()) as usize;
+        let Some((str_len, varint_size)) = read_varuint(&block[i + 5..]) else {
+            i += 1;
+            continue;
+        };
+        let flag_pos = i + 5 + varint_size;
+        let text_start = flag_pos + 1;
+        let text_end = text_start + str_len as usize;
+        if str_len > 0
+            && length == varint_size + 1 + str_len as usize
+            && text_end <= block.len()
+            && block[flag_pos] <= 1
+        {
+            if let Ok(text) = std::str::from_utf8(&block[text_start..text_end]) {
+                strings.push(text.to_string());
+                i = text_end;
+                continue;
+            }
+        }
+        i += 1;
+    }
+    strings
+}
+
+fn read_varuint(data: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for (i, byte) in data.iter().enumerate().take(10) {
+        value |= ((byte & 0x7F) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_to_text() {
+        let html = "<html><head><style>p { color: red; }</style></head>\n\
+                    <body><p>Fish &amp; chips</p>\n<p>caf&#233;&nbsp;au  lait</p></body></html>";
+        assert_eq!(html_to_text(html), "Fish & chips\ncafé au lait");
+    }
+
+    #[test]
+    fn test_extract_rm_text() {
+        let text = b"Hello world";
+        let mut block = vec![0x1F, 0x00, 0x01];
+        block.push(0x5C);
+        block.extend_from_slice(&((text.len() + 2) as u32).to_le_bytes());
+        block.push(text.len() as u8);
+        block.push(1);
+        block.extend_from_slice(text);
+
+        let mut data = b"reMarkable .lines file, version=6          ".to_vec();
+        data.extend_from_slice(&(block.len() as u32).to_le_bytes());
+        data.extend_from_slice(&[0, 1, 1, RM_BLOCK_ROOT_TEXT]);
+        data.extend_from_slice(&block);
+
+        assert_eq!(extract_rm(&data), "Hello world");
+        assert_eq!(extract_rm(b"not an rm file"), "");
+    }
+
+    #[test]
+    fn test_extract_highlights() {
+        let json = br#"{"highlights": [[{"text": "first"}, {"text": "second"}]]}"#;
+        assert_eq!(extract_highlights(json), "first\nsecond");
+    }
+}
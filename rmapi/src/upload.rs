@@ -0,0 +1,318 @@
+use crate::error::Error;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Extensions of the local files that can be uploaded as documents
+pub const SUPPORTED_EXTENSIONS: [&str; 2] = ["pdf", "epub"];
+
+/// A folder or document that [`plan_upload`] decided to upload.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedUpload {
+    pub local_path: PathBuf,
+    /// Visible names from the target folder down to this entry
+    pub remote_path: PathBuf,
+    /// ID the entry is created with, so children can reference their folder
+    pub id: String,
+    /// ID of the folder created for the parent directory, `None` for
+    /// entries placed directly into the target folder
+    pub parent: Option<String>,
+    pub is_folder: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct UploadReport {
+    /// Uploaded documents as (local path, remote path below the target folder)
+    pub uploaded: Vec<(PathBuf, PathBuf)>,
+    /// Local directories that were created as folders, like `uploaded`
+    pub folders: Vec<(PathBuf, PathBuf)>,
+    /// Local paths that were left out, with the reason
+    pub skipped: Vec<(PathBuf, String)>,
+    pub failed: Vec<(PathBuf, Error)>,
+}
+
+/// Outcome of [`crate::RmClient::replace_document`].
+#[derive(Debug, Clone, Default)]
+pub struct ReplaceReport {
+    /// Pages of the document, including pages inserted on the tablet
+    pub pages_before: u32,
+    pub pages_after: u32,
+    /// Pages past the end of the new PDF, removed with their annotations
+    pub removed_pages: Vec<String>,
+}
+
+/// Counts the pages of a PDF.
+pub fn pdf_page_count(data: &[u8]) -> Result<u32, Error> {
+    let doc = lopdf::Document::load_mem(data)
+        .map_err(|e| Error::Message(format!("Failed to parse PDF: {}", e)))?;
+    Ok(doc.get_pages().len() as u32)
+}
+
+/// Adapts a `.content` file to a PDF that now has `page_count` pages.
+///
+/// Pages keep their IDs, and with them their annotations, as long as the
+/// PDF page they show still exists. Pages pointing past the end of the new
+/// PDF are removed and pages are added for PDF pages that had none. Pages
+/// inserted on the tablet are not backed by the PDF and always stay, so
+/// `pageCount` ends up as the number of remaining pages, which can exceed
+/// `page_count`. Returns the IDs of the removed pages.
+pub fn remap_content(content: &mut Value, page_count: u32) -> Vec<String> {
+    let mut removed = Vec::new();
+    let mut remaining = page_count;
+    let page_id = |page: &Value| page.get("id").and_then(Value::as_str).map(str::to_string);
+
+    if let Some(pages) = content
+        .pointer_mut("/cPages/pages")
+        .and_then(Value::as_array_mut)
+    {
+        let redirect = |page: &Value| page.pointer("/redir/value").and_then(Value::as_u64);
+        pages.retain(|page| match redirect(page) {
+            Some(index) if index >= u64::from(page_count) => {
+                removed.extend(page_id(page));
+                false
+            }
+            _ => true,
+        });
+
+        // Order comes from the lexicographically sorted `idx` keys, and a
+        // key extended by any character sorts after the key itself
+        let mapped: HashSet<u64> = pages.iter().filter_map(redirect).collect();
+        let mut idx = pages
+            .iter()
+            .filter_map(|page| page.pointer("/idx/value").and_then(Value::as_str))
+            .max()
+            .unwrap_or("b")
+            .to_string();
+        for index in (0..u64::from(page_count)).filter(|index| !mapped.contains(index)) {
+            idx.push('n');
+            pages.push(json!({
+                "id": Uuid::new_v4().to_string(),
+                "idx": { "timestamp": "1:2", "value": idx },
+                "redir": { "timestamp": "1:2", "value": index },
+            }));
+        }
+        remaining = pages
+            .iter()
+            .filter(|page| page.get("deleted").is_none())
+            .count() as u32;
+        if let Some(original) = content.pointer_mut("/cPages/original/value") {
+            *original = page_count.into();
+        }
+    } else if let Some(pages) = content.get_mut("pages").and_then(Value::as_array_mut) {
+        let page_count = page_count as usize;
+        if pages.len() > page_count {
+            removed.extend(
+                pages
+                    .drain(page_count..)
+                    .filter_map(|page| page.as_str().map(str::to_string)),
+            );
+        }
+        while pages.len() < page_count {
+            pages.push(Uuid::new_v4().to_string().into());
+        }
+    }
+
+    if let Some(object) = content.as_object_mut() {
+        object.insert("pageCount".to_string(), remaining.into());
+        if object.contains_key("originalPageCount") {
+            object.insert("originalPageCount".to_string(), page_count.into());
+        }
+    }
+    removed
+}
+
+/// Rebuilds a `.pagedata` file, one template name per page, for a document
+/// whose pages changed from `old_pages` to `new_pages`, both page IDs in
+/// document order. Pages keep their template, new pages get the blank one.
+pub fn remap_pagedata(pagedata: &str, old_pages: &[String], new_pages: &[String]) -> String {
+    let templates: HashMap<&str, &str> = old_pages
+        .iter()
+        .map(String::as_str)
+        .zip(pagedata.lines())
+        .collect();
+    new_pages
+        .iter()
+        .map(|id| format!("{}\n", templates.get(id.as_str()).unwrap_or(&"Blank")))
+        .collect()
+}
+
+pub fn is_supported(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            SUPPORTED_EXTENSIONS
+                .iter()
+                .any(|supported| ext.eq_ignore_ascii_case(supported))
+        })
+}
+
+/// Walks `paths` and lists what has to be uploaded, folders before their
+/// contents. Directories are only descended into when `recursive` is set;
+/// hidden entries inside them are ignored. Everything that cannot be
+/// uploaded ends up in the returned report as skipped.
+pub fn plan_upload(
+    paths: &[PathBuf],
+    recursive: bool,
+) -> Result<(Vec<PlannedUpload>, UploadReport), Error> {
+    let mut planned = Vec::new();
+    let mut report = UploadReport::default();
+    for path in paths {
+        let metadata =
+            fs::metadata(path).map_err(|e| Error::Message(format!("{}: {}", path.display(), e)))?;
+        if metadata.is_dir() && !recursive {
+            report
+                .skipped
+                .push((path.clone(), "is a directory (use -r)".to_string()));
+            continue;
+        }
+        plan_entry(path, Path::new(""), None, &mut planned, &mut report)?;
+    }
+    Ok((planned, report))
+}
+
+fn plan_entry(
+    path: &Path,
+    prefix: &Path,
+    parent: Option<&str>,
+    planned: &mut Vec<PlannedUpload>,
+    report: &mut UploadReport,
+) -> Result<(), Error> {
+    // Canonicalizing gives `.` and `..` a real name
+    let name = match path.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => fs::canonicalize(path)?
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "/".to_string()),
+    };
+    let remote_path = prefix.join(&name);
+
+    if !path.is_dir() {
+        if is_supported(path) {
+            planned.push(PlannedUpload {
+                local_path: path.to_path_buf(),
+                remote_path,
+                id: Uuid::new_v4().to_string(),
+                parent: parent.map(str::to_string),
+                is_folder: false,
+            });
+        } else {
+            report
+                .skipped
+                .push((path.to_path_buf(), "unsupported file type".to_string()));
+        }
+        return Ok(());
+    }
+
+    let id = Uuid::new_v4().to_string();
+    planned.push(PlannedUpload {
+        local_path: path.to_path_buf(),
+        remote_path: remote_path.clone(),
+        id: id.clone(),
+        parent: parent.map(str::to_string),
+        is_folder: true,
+    });
+
+    let mut children = fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    children.sort();
+    for child in children {
+        let hidden = child
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if !hidden {
+            plan_entry(&child, &remote_path, Some(&id), planned, report)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_supported() {
+        assert!(is_supported(Path::new("paper.pdf")));
+        assert!(is_supported(Path::new("dir/Book.EPUB")));
+        assert!(!is_supported(Path::new("notes.txt")));
+        assert!(!is_supported(Path::new("pdf")));
+    }
+
+    #[test]
+    fn test_remap_flat_pages() {
+        let mut content = json!({ "pageCount": 3, "pages": ["a", "b", "c"] });
+        assert_eq!(remap_content(&mut content, 2), vec!["c".to_string()]);
+        assert_eq!(content["pages"], json!(["a", "b"]));
+        assert_eq!(content["pageCount"], 2);
+
+        assert!(remap_content(&mut content, 4).is_empty());
+        let pages = content["pages"].as_array().unwrap();
+        assert_eq!(pages.len(), 4);
+        assert_eq!(pages[..2], [json!("a"), json!("b")]);
+    }
+
+    #[test]
+    fn test_remap_pagedata() {
+        let ids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+        // The page inserted on the tablet keeps its template
+        assert_eq!(
+            remap_pagedata(
+                "Lined\nGrid\nDots\nBlank\n",
+                &ids(&["a", "inserted", "b", "c"]),
+                &ids(&["a", "inserted", "b"]),
+            ),
+            "Lined\nGrid\nDots\n"
+        );
+        assert_eq!(
+            remap_pagedata("Lined\n", &ids(&["a"]), &ids(&["a", "new"])),
+            "Lined\nBlank\n"
+        );
+        assert_eq!(remap_pagedata("", &[], &[]), "");
+    }
+
+    #[test]
+    fn test_remap_cpages() {
+        let page = |id: &str, idx: &str, redir: Option<u32>| {
+            let mut page = json!({ "id": id, "idx": { "timestamp": "1:2", "value": idx } });
+            if let Some(redir) = redir {
+                page["redir"] = json!({ "timestamp": "1:2", "value": redir });
+            }
+            page
+        };
+        let mut content = json!({
+            "pageCount": 3,
+            "cPages": {
+                "original": { "timestamp": "1:1", "value": 3 },
+                "pages": [
+                    page("a", "ba", Some(0)),
+                    page("inserted", "bb", None),
+                    page("b", "bc", Some(1)),
+                    page("c", "bd", Some(2)),
+                ],
+            },
+        });
+
+        assert_eq!(remap_content(&mut content, 2), vec!["c".to_string()]);
+        let ids: Vec<&str> = content["cPages"]["pages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|page| page["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids, ["a", "inserted", "b"]);
+        assert_eq!(content["cPages"]["original"]["value"], 2);
+        // The inserted page is not part of the PDF but still counts
+        assert_eq!(content["pageCount"], 3);
+
+        assert!(remap_content(&mut content, 3).is_empty());
+        let pages = content["cPages"]["pages"].as_array().unwrap();
+        assert_eq!(pages.len(), 4);
+        assert_eq!(pages[3]["redir"]["value"], 2);
+        assert!(pages[3]["idx"]["value"].as_str().unwrap() > "bc");
+        assert_eq!(content["pageCount"], 4);
+    }
+}
@@ -0,0 +1,132 @@
+use crate::error::Error;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// What a file inside a docSchema holds, used to break storage down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SubfileKind {
+    Pdf,
+    Epub,
+    /// Handwritten `.rm` pages
+    Pages,
+    Thumbnails,
+    /// `.metadata`, `.content`, `.pagedata` and similar small JSON files
+    Metadata,
+    Other,
+}
+
+impl SubfileKind {
+    /// Classifies a docSchema entry by its file name, e.g. `<id>/<page>.rm`.
+    pub fn from_file_name(name: &str) -> Self {
+        let (dir, file) = match name.rsplit_once('/') {
+            Some((dir, file)) => (dir, file),
+            None => ("", name),
+        };
+        if dir.ends_with(".thumbnails") {
+            return SubfileKind::Thumbnails;
+        }
+        match file.rsplit_once('.').map(|(_, ext)| ext) {
+            Some("pdf") => SubfileKind::Pdf,
+            Some("epub") => SubfileKind::Epub,
+            Some("rm") => SubfileKind::Pages,
+            Some("metadata" | "content" | "pagedata" | "local" | "json") => SubfileKind::Metadata,
+            _ => SubfileKind::Other,
+        }
+    }
+}
+
+impl fmt::Display for SubfileKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SubfileKind::Pdf => "pdf",
+            SubfileKind::Epub => "epub",
+            SubfileKind::Pages => "pages",
+            SubfileKind::Thumbnails => "thumbnails",
+            SubfileKind::Metadata => "metadata",
+            SubfileKind::Other => "other",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Storage used by a document or folder, per kind of subfile.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SizeBreakdown {
+    pub sizes: BTreeMap<SubfileKind, u64>,
+}
+
+impl SizeBreakdown {
+    pub fn add(&mut self, kind: SubfileKind, size: u64) {
+        *self.sizes.entry(kind).or_default() += size;
+    }
+
+    pub fn merge(&mut self, other: &SizeBreakdown) {
+        for (kind, size) in &other.sizes {
+            self.add(*kind, *size);
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.sizes.values().sum()
+    }
+}
+
+/// Parses a human-readable size such as `8G`, `512MiB` or `1.5 GB`.
+///
+/// Units are binary (a `G` is 1024³ bytes) with or without the `iB`/`B`
+/// suffix; a bare number is taken as bytes.
+pub fn parse_size(value: &str) -> Result<u64, Error> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| Error::Message(format!("Invalid size: {}", value)))?;
+
+    let unit = unit.trim().to_ascii_uppercase();
+    let unit = unit
+        .strip_suffix("IB")
+        .or_else(|| unit.strip_suffix('B'))
+        .unwrap_or(&unit);
+    let exponent = match unit {
+        "" => 0,
+        "K" => 1,
+        "M" => 2,
+        "G" => 3,
+        "T" => 4,
+        _ => return Err(Error::Message(format!("Invalid size unit: {}", value))),
+    };
+    Ok((number * 1024f64.powi(exponent)) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subfile_kind() {
+        let id = "3f2504e0-4f89-11d3-9a0c-0305e82c3301";
+        let kind = |suffix: &str| SubfileKind::from_file_name(&format!("{}{}", id, suffix));
+        assert_eq!(kind(".pdf"), SubfileKind::Pdf);
+        assert_eq!(kind(".epub"), SubfileKind::Epub);
+        assert_eq!(kind("/page-1.rm"), SubfileKind::Pages);
+        assert_eq!(kind(".thumbnails/page-1.png"), SubfileKind::Thumbnails);
+        assert_eq!(kind(".content"), SubfileKind::Metadata);
+        assert_eq!(kind(".textconversion/page-1.json"), SubfileKind::Metadata);
+        assert_eq!(kind(""), SubfileKind::Other);
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("100").unwrap(), 100);
+        assert_eq!(parse_size("8G").unwrap(), 8 << 30);
+        assert_eq!(parse_size("512 MiB").unwrap(), 512 << 20);
+        assert_eq!(parse_size("1.5kb").unwrap(), 1536);
+        assert!(parse_size("lots").is_err());
+        assert!(parse_size("5 parsecs").is_err());
+    }
+}
@@ -1,16 +1,31 @@
-use clap::Parser;
+use clap::{CommandFactory, Parser};
+use rmapi::cache::{BlobCache, DEFAULT_MAX_CACHE_SIZE};
+use std::io::IsTerminal;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 mod rmclient;
 use crate::rmclient::actions;
-use crate::rmclient::commands::Commands;
+use crate::rmclient::commands::{Commands, DuSort};
+use crate::rmclient::completions;
+use crate::rmclient::convert::{default_converters_path, ConverterRegistry};
 use crate::rmclient::error::Error;
+use crate::rmclient::output::{ChangeRecord, OutputFormat};
+use crate::rmclient::progress;
 use crate::rmclient::token::{
     client_from_registration_code, client_from_token_file, default_token_file_path,
 };
+use crate::rmclient::watch;
+
+const PATH_HELP: &str = "\
+Remote paths:
+  /Papers/Notes        Absolute path, or relative to the shell's current directory
+  Notes@4f3c1a2b       Picks one of several entries sharing a name by ID prefix
+  id:4f3c1a2b          Addresses a document by (a prefix of) its ID, wherever it is
+  id:4f3c1a2b/Notes    Paths can continue below an ID selector";
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, after_help = PATH_HELP)]
 struct Args {
     #[arg(
         short = 't',
@@ -21,6 +36,10 @@ struct Args {
     )]
     auth_token_file: PathBuf,
 
+    /// Format of the results printed by commands
+    #[arg(long, global = true, env = "RMAPI_OUTPUT", value_enum, default_value_t)]
+    output: OutputFormat,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -30,54 +49,230 @@ async fn main() {
     env_logger::init();
     let args = Args::parse();
 
-    if let Err(err) = run(args).await {
+    let result = run(args).await;
+    // Once per command; pruning scans the whole cache
+    match BlobCache::open_default() {
+        Ok(cache) => {
+            if let Err(e) = cache.prune(DEFAULT_MAX_CACHE_SIZE) {
+                log::warn!("Failed to prune blob cache: {}", e);
+            }
+        }
+        Err(e) => log::debug!("No blob cache to prune: {}", e),
+    }
+    if let Err(err) = result {
         eprintln!("Error: {}", err);
         std::process::exit(1);
     }
 }
 
 async fn run(args: Args) -> Result<(), Error> {
+    let output = args.output;
     match args.command {
         Commands::Register { code } => {
             let _client = client_from_registration_code(&code, &args.auth_token_file).await?;
-            println!(
-                "Registration successful! Token saved to {:?}",
-                args.auth_token_file
-            );
+            let record = ChangeRecord::new("registered", &args.auth_token_file);
+            output.item(&record, |record| {
+                format!("Registration successful! Token saved to {:?}", record.path)
+            })?;
         }
-        Commands::Ls { path } => {
+        Commands::Ls { path, options } => {
             let client = client_from_token_file(&args.auth_token_file).await?;
             let target_path = path.as_deref().unwrap_or(Path::new("/"));
-            actions::ls(&client, target_path).await?;
+            actions::ls(&client, output, target_path, &options).await?;
         }
-        Commands::Shell => {
+        Commands::Tree { path, options } => {
             let client = client_from_token_file(&args.auth_token_file).await?;
-            let mut shell = crate::rmclient::shell::Shell::new(client, args.auth_token_file);
-            shell.run().await?;
+            let target_path = path
+                .map(|p| rmapi::filesystem::normalize_path(&p, Path::new("/")))
+                .unwrap_or_else(|| PathBuf::from("/"));
+            actions::tree(&client, output, &target_path, options.depth, options.size)?;
         }
-        Commands::Put { path, destination } => {
-            let mut client = client_from_token_file(&args.auth_token_file).await?;
-            let destination_path =
-                destination.map(|dest| rmapi::filesystem::normalize_path(&dest, Path::new("/")));
-            actions::put(&mut client, &path, destination_path.as_deref()).await?;
+        Commands::Du { path, options } => {
+            let client = client_from_token_file(&args.auth_token_file).await?;
+            let target_path = path
+                .map(|p| rmapi::filesystem::normalize_path(&p, Path::new("/")))
+                .unwrap_or_else(|| PathBuf::from("/"));
+            actions::du(
+                &client,
+                output,
+                &target_path,
+                options.sort == DuSort::Size,
+                options.quota,
+            )
+            .await?;
+        }
+        Commands::Stat { path, json } => {
+            let client = client_from_token_file(&args.auth_token_file).await?;
+            let target_path = rmapi::filesystem::normalize_path(&path, Path::new("/"));
+            let output = if json { OutputFormat::Json } else { output };
+            actions::stat(&client, output, &target_path).await?;
         }
-        Commands::Rm { path } => {
+        Commands::Shell { script } => {
             let client = client_from_token_file(&args.auth_token_file).await?;
-            let normalized_path = rmapi::filesystem::normalize_path(&path, Path::new("/"));
-            actions::rm(&client, &normalized_path).await?;
+            let mut shell =
+                crate::rmclient::shell::Shell::new(client, args.auth_token_file, output);
+            match script {
+                Some(path) => {
+                    let commands = std::fs::read_to_string(&path)?;
+                    shell
+                        .run_script(&commands, &path.display().to_string())
+                        .await?;
+                }
+                None if !std::io::stdin().is_terminal() => {
+                    let commands = std::io::read_to_string(std::io::stdin())?;
+                    shell.run_script(&commands, "<stdin>").await?;
+                }
+                None => shell.run().await?,
+            }
         }
-        Commands::Get { path, recursive } => {
+        Commands::Put {
+            paths,
+            replace: Some(remote),
+            ..
+        } => {
+            let [path] = paths.as_slice() else {
+                return Err(Error::Message(
+                    "--replace takes exactly one local file".to_string(),
+                ));
+            };
+            let mut client = client_from_token_file(&args.auth_token_file).await?;
+            client.set_progress(progress::reporter());
+            let target = rmapi::filesystem::normalize_path(&remote, Path::new("/"));
+            actions::replace(&client, output, path, &target).await?;
+        }
+        Commands::Put {
+            paths,
+            dest,
+            recursive,
+            replace: None,
+        } => {
+            let mut client = client_from_token_file(&args.auth_token_file).await?;
+            client.set_progress(progress::reporter());
+            let (sources, destination) =
+                actions::split_destination(&paths, dest.as_ref(), Path::new("."));
+            let destination_path = destination
+                .map(|dest| rmapi::filesystem::normalize_path(dest, Path::new("/")))
+                .unwrap_or_else(|| PathBuf::from("/"));
+            let converters = ConverterRegistry::load(&default_converters_path())?;
+            actions::put(
+                &client,
+                output,
+                sources,
+                &destination_path,
+                recursive,
+                &converters,
+            )
+            .await?;
+        }
+        Commands::PutHtml {
+            file,
+            destination,
+            title,
+        } => {
+            let mut client = client_from_token_file(&args.auth_token_file).await?;
+            client.set_progress(progress::reporter());
+            let destination_path = destination
+                .map(|dest| rmapi::filesystem::normalize_path(&dest, Path::new("/")))
+                .unwrap_or_else(|| PathBuf::from("/"));
+            actions::put_html(&client, output, &file, &destination_path, title.as_deref()).await?;
+        }
+        Commands::Rm { paths } => {
             let client = client_from_token_file(&args.auth_token_file).await?;
-            let normalized_path = rmapi::filesystem::normalize_path(&path, Path::new("/"));
-            actions::get(&client, &normalized_path, recursive).await?;
+            actions::rm(&client, output, &normalize_paths(&paths)).await?;
+        }
+        Commands::Get { paths, options } => {
+            let mut client = client_from_token_file(&args.auth_token_file).await?;
+            client.set_progress(progress::reporter());
+            let destination = options.dest.clone().unwrap_or_else(|| PathBuf::from("."));
+            actions::get(
+                &client,
+                output,
+                &normalize_paths(&paths),
+                &destination,
+                (&options).into(),
+            )
+            .await?;
         }
-        Commands::Mv { path, destination } => {
+        Commands::Mv { paths, destination } => {
             let client = client_from_token_file(&args.auth_token_file).await?;
-            let normalized_path = rmapi::filesystem::normalize_path(&path, Path::new("/"));
             let normalized_destination =
                 rmapi::filesystem::normalize_path(&destination, Path::new("/"));
-            actions::mv(&client, &normalized_path, &normalized_destination).await?;
+            actions::mv(
+                &client,
+                output,
+                &normalize_paths(&paths),
+                &normalized_destination,
+            )
+            .await?;
+        }
+        Commands::Backup { dir, prune } => {
+            let client = client_from_token_file(&args.auth_token_file).await?;
+            actions::backup(&client, output, &dir, prune).await?;
+        }
+        Commands::RestoreBackup { dir, only, into } => {
+            let client = client_from_token_file(&args.auth_token_file).await?;
+            let into_path =
+                into.map(|into| rmapi::filesystem::normalize_path(&into, Path::new("/")));
+            actions::restore_backup(&client, output, &dir, only.as_deref(), into_path.as_deref())
+                .await?;
+        }
+        Commands::Find { root, filters } => {
+            let client = client_from_token_file(&args.auth_token_file).await?;
+            let root_path = root.unwrap_or_else(|| PathBuf::from("/"));
+            actions::find(&client, output, &root_path, &filters.into())?;
+        }
+        Commands::Grep {
+            pattern,
+            path,
+            ignore_case,
+        } => {
+            let client = client_from_token_file(&args.auth_token_file).await?;
+            let target_path = path
+                .map(|p| rmapi::filesystem::normalize_path(&p, Path::new("/")))
+                .unwrap_or_else(|| PathBuf::from("/"));
+            actions::grep(&client, output, &pattern, &target_path, ignore_case).await?;
+        }
+        Commands::Watch {
+            local_dir,
+            remote_dir,
+            options,
+        } => {
+            let mut client = client_from_token_file(&args.auth_token_file).await?;
+            let remote_path = rmapi::filesystem::normalize_path(&remote_dir, Path::new("/"));
+            watch::watch(
+                &mut client,
+                output,
+                &args.auth_token_file,
+                &local_dir,
+                &remote_path,
+                &options,
+            )
+            .await?;
+        }
+        Commands::WatchRemote { interval, exec } => {
+            let mut client = client_from_token_file(&args.auth_token_file).await?;
+            watch::watch_remote(
+                &mut client,
+                output,
+                &args.auth_token_file,
+                Duration::from_secs(interval),
+                exec.as_deref(),
+            )
+            .await?;
+        }
+        Commands::Completions { shell } => {
+            completions::print_completions(shell, &mut Args::command())?;
+        }
+        Commands::Complete { partial } => {
+            completions::complete_remote(&partial)?;
         }
     }
     Ok(())
 }
+
+fn normalize_paths(paths: &[PathBuf]) -> Vec<PathBuf> {
+    paths
+        .iter()
+        .map(|path| rmapi::filesystem::normalize_path(path, Path::new("/")))
+        .collect()
+}
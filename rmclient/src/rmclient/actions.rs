@@ -1,76 +1,817 @@
-use std::path::Path;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
 
+use rmapi::backup::RemovedPolicy;
+use rmapi::client::{DownloadOptions, UploadOptions};
+use rmapi::constants::TRASH_ID;
+use rmapi::epub::package_html;
+use rmapi::filesystem::{sanitize_file_name, FindQuery};
+use rmapi::objects::{DocumentInfo, Node, NodeKind};
+use rmapi::search::{compile_pattern, SearchIndex};
+use rmapi::usage::{SizeBreakdown, SubfileKind};
 use rmapi::RmClient;
 
+use crate::rmclient::commands::{LsArgs, LsSort};
+use crate::rmclient::convert::ConverterRegistry;
 use crate::rmclient::error::Error;
+use crate::rmclient::output::{
+    BackupSummary, ChangeRecord, EntryRecord, FailureRecord, MatchRecord, OutputFormat,
+    ReplaceRecord, RestoreSummary, SkippedRecord, UploadSummary, UsageRecord, UsageReport,
+};
 
-pub async fn ls(client: &RmClient, path: &Path) -> Result<(), Error> {
-    let entries = client.filesystem.list_dir(Some(path))?;
+pub async fn ls(
+    client: &RmClient,
+    output: OutputFormat,
+    path: &Path,
+    options: &LsArgs,
+) -> Result<(), Error> {
+    let node = client.filesystem.find_node_by_path(path)?;
+    let mut sections = Vec::new();
+    if node.is_directory() {
+        collect_listing(node, path, options, &mut sections);
+    } else {
+        sections.push((path.to_path_buf(), vec![EntryRecord::new(path, node)]));
+    }
+
+    if !output.is_table() {
+        let records: Vec<EntryRecord> = sections
+            .into_iter()
+            .flat_map(|(_, records)| records)
+            .collect();
+        return output.records(&records);
+    }
+
+    for (i, (dir, records)) in sections.iter().enumerate() {
+        if options.recursive {
+            if i > 0 {
+                println!();
+            }
+            println!("{}:", dir.display());
+        }
+        for record in records {
+            println!("{}", format_entry(record, options.long));
+        }
+    }
+    Ok(())
+}
+
+/// Lists `node` and, for `-R`, every directory below it, one section per directory.
+fn collect_listing(
+    node: &Node,
+    path: &Path,
+    options: &LsArgs,
+    sections: &mut Vec<(PathBuf, Vec<EntryRecord>)>,
+) {
+    let mut entries = node.listing();
+    match options.sort {
+        LsSort::Name => {}
+        LsSort::Mtime => entries.sort_by_key(|(_, node)| Reverse(node.document.last_modified)),
+        LsSort::Size => entries.sort_by_key(|(_, node)| Reverse(node.total_size())),
+        LsSort::Created => entries.sort_by_key(|(_, node)| Reverse(node.document.created)),
+    }
+    if options.reverse {
+        entries.reverse();
+    }
+
+    let kind = options.node_type.map(NodeKind::from);
+    let records = entries
+        .iter()
+        .filter(|(_, child)| kind.is_none_or(|kind| child.kind() == kind))
+        .map(|(label, child)| EntryRecord::new(&path.join(label), child))
+        .collect();
+    sections.push((path.to_path_buf(), records));
+
+    if options.recursive {
+        for (label, child) in entries {
+            // Like find, only descend into the trash when listing it directly
+            if child.is_directory() && child.id() != TRASH_ID {
+                collect_listing(child, &path.join(label), options, sections);
+            }
+        }
+    }
+}
 
-    for node in entries {
-        let suffix = if node.is_directory() { "/" } else { "" };
-        let last_modified = node.document.last_modified.format("%Y-%m-%d %H:%M:%S");
+fn format_entry(record: &EntryRecord, long: bool) -> String {
+    let is_directory = record.kind == NodeKind::Directory;
+    let name = record
+        .path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let label = format!("{}{}", name, if is_directory { "/" } else { "" });
+    let last_modified = record.modified.format("%Y-%m-%d %H:%M:%S");
+    if !long {
+        return format!("{:<40}  {}", label, last_modified);
+    }
+
+    let pages = record
+        .pages
+        .map(|pages| pages.to_string())
+        .unwrap_or_else(|| "-".to_string());
+    let tags = if record.tags.is_empty() {
+        String::new()
+    } else {
+        format!("  [{}]", record.tags.join(", "))
+    };
+    format!(
+        "{}{}  {:>10}  {:>5}  {}  {}  {}{}",
+        if is_directory { 'd' } else { '-' },
+        if record.pinned { '*' } else { ' ' },
+        format_size(record.size),
+        pages,
+        last_modified,
+        record.id,
+        label,
+        tags
+    )
+}
+
+pub fn find(
+    client: &RmClient,
+    output: OutputFormat,
+    root: &Path,
+    query: &FindQuery,
+) -> Result<(), Error> {
+    let records: Vec<EntryRecord> = client
+        .filesystem
+        .find(root, query)?
+        .into_iter()
+        .map(|(path, node)| EntryRecord::new(&path, node))
+        .collect();
+    output.list(&records, |record| record.path.display().to_string())
+}
+
+pub fn tree(
+    client: &RmClient,
+    output: OutputFormat,
+    path: &Path,
+    depth: Option<usize>,
+    show_size: bool,
+) -> Result<(), Error> {
+    let node = client.filesystem.find_node_by_path(path)?;
+    if !output.is_table() {
+        let mut records = vec![EntryRecord::new(path, node)];
+        collect_tree(node, path, depth, &mut records);
+        return output.records(&records);
+    }
+    if !node.is_directory() {
         println!(
-            "{:<40}  {}",
-            format!("{}{}", node.name(), suffix),
-            last_modified
+            "{}",
+            tree_label(path.display().to_string(), node, show_size)
         );
+        return Ok(());
     }
+
+    // The trash is only shown when asked for explicitly
+    let children: Vec<(String, &Node)> = node
+        .listing()
+        .into_iter()
+        .filter(|(_, child)| child.id() != TRASH_ID)
+        .collect();
+    let count: usize = children
+        .iter()
+        .map(|(_, child)| child.document_count() + usize::from(!child.is_directory()))
+        .sum();
+    let size: u64 = children.iter().map(|(_, child)| child.total_size()).sum();
+
+    println!(
+        "{}",
+        folder_summary(path.display().to_string(), count, size, show_size)
+    );
+    print_tree(&children, "", depth, show_size);
     Ok(())
 }
 
-pub async fn rm(client: &RmClient, path: &Path) -> Result<(), Error> {
-    let node = client.filesystem.find_node_by_path(path)?;
+/// Collects the records `print_tree` would show, depth first.
+fn collect_tree(node: &Node, path: &Path, depth: Option<usize>, records: &mut Vec<EntryRecord>) {
+    if depth == Some(0) {
+        return;
+    }
+    for (label, child) in node.listing() {
+        if child.id() == TRASH_ID {
+            continue;
+        }
+        let child_path = path.join(label);
+        records.push(EntryRecord::new(&child_path, child));
+        if child.is_directory() {
+            collect_tree(child, &child_path, depth.map(|d| d - 1), records);
+        }
+    }
+}
 
-    client
-        .delete_entry(&node.document)
+fn print_tree(children: &[(String, &Node)], prefix: &str, depth: Option<usize>, show_size: bool) {
+    if depth == Some(0) {
+        return;
+    }
+    for (i, (label, child)) in children.iter().enumerate() {
+        let last = i + 1 == children.len();
+        let branch = if last { "└── " } else { "├── " };
+        println!(
+            "{}{}{}",
+            prefix,
+            branch,
+            tree_label(label.clone(), child, show_size)
+        );
+        if child.is_directory() {
+            let child_prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+            print_tree(
+                &child.listing(),
+                &child_prefix,
+                depth.map(|d| d - 1),
+                show_size,
+            );
+        }
+    }
+}
+
+fn tree_label(label: String, node: &Node, show_size: bool) -> String {
+    if node.is_directory() {
+        folder_summary(
+            format!("{}/", label),
+            node.document_count(),
+            node.total_size(),
+            show_size,
+        )
+    } else if show_size {
+        format!("{} ({})", label, format_size(node.total_size()))
+    } else {
+        label
+    }
+}
+
+fn folder_summary(label: String, count: usize, size: u64, show_size: bool) -> String {
+    let noun = if count == 1 { "document" } else { "documents" };
+    if show_size {
+        format!("{} ({} {}, {})", label, count, noun, format_size(size))
+    } else {
+        format!("{} ({} {})", label, count, noun)
+    }
+}
+
+pub async fn stat(client: &RmClient, output: OutputFormat, path: &Path) -> Result<(), Error> {
+    let node = client.filesystem.find_node_by_path(path)?;
+    let info = client
+        .document_info(&node.id())
         .await
         .map_err(Error::Rmapi)?;
 
-    println!("Removed {}", path.display());
-    Ok(())
+    output.item(&info, |info| format_info(path, info))
 }
 
-pub async fn put(
-    client: &mut RmClient,
+fn format_info(path: &Path, info: &DocumentInfo) -> String {
+    let format_time = |time: Option<chrono::DateTime<chrono::Utc>>| {
+        time.map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_else(|| "-".to_string())
+    };
+    let parent = match info.parent.as_str() {
+        "" => "(root)",
+        parent => parent,
+    };
+    let mut lines = vec![
+        format!("Path:      {}", path.display()),
+        format!("ID:        {}", info.id),
+        format!("Name:      {}", info.name),
+        format!("Parent:    {}", parent),
+        format!("Type:      {}", info.doc_type),
+        format!("Version:   {}", info.version),
+        format!("Created:   {}", format_time(info.created)),
+        format!("Modified:  {}", format_time(info.last_modified)),
+        format!("Pinned:    {}", if info.pinned { "yes" } else { "no" }),
+    ];
+    if let Some(file_type) = &info.file_type {
+        lines.push(format!("File type: {}", file_type));
+    }
+    if let Some(page_count) = info.page_count {
+        lines.push(format!("Pages:     {}", page_count));
+    }
+    if !info.tags.is_empty() {
+        lines.push(format!("Tags:      {}", info.tags.join(", ")));
+    }
+    lines.push(format!("Hash:      {}", info.hash));
+    lines.push(format!(
+        "Size:      {} ({} bytes)",
+        format_size(info.size),
+        info.size
+    ));
+    lines.push("Subfiles:".to_string());
+    for subfile in &info.subfiles {
+        lines.push(format!(
+            "  {}  {:>10}  {}",
+            subfile.hash, subfile.size, subfile.id
+        ));
+    }
+    lines.join("\n")
+}
+
+pub async fn du(
+    client: &RmClient,
+    output: OutputFormat,
     path: &Path,
-    destination: Option<&Path>,
+    sort_by_size: bool,
+    quota: Option<u64>,
 ) -> Result<(), Error> {
-    if path.extension() != Some("pdf".as_ref()) {
-        return Err(Error::Message("Only PDF files are supported".to_string()));
+    let node = client.filesystem.find_node_by_path(path)?;
+    let mut entries = vec![(path.to_path_buf(), node)];
+    if node.is_directory() {
+        entries.extend(client.filesystem.find(path, &FindQuery::default())?);
     }
 
-    let parent_id = match destination {
-        Some(dest) if !dest.as_os_str().is_empty() => {
-            let node = client.filesystem.find_node_by_path(dest)?;
-            if !node.is_directory() {
-                return Err(Error::Message(format!(
-                    "Destination is not a directory: {}",
-                    dest.display()
-                )));
+    let ids: Vec<String> = entries.iter().map(|(_, node)| node.id()).collect();
+    let usage = client.storage_usage(&ids).await?;
+
+    let mut entries: Vec<UsageRecord> = entries
+        .into_iter()
+        .map(|(entry_path, node)| {
+            let breakdown = subtree_usage(node, &usage);
+            UsageRecord {
+                path: entry_path,
+                size: breakdown.total(),
+                breakdown: breakdown.sizes,
             }
-            Some(node.id().to_string())
+        })
+        .collect();
+    let total = entries[0].clone();
+    if sort_by_size {
+        entries.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+    }
+
+    let report = UsageReport {
+        entries,
+        total,
+        account_size: client.filesystem.tree.root.total_size(),
+        quota,
+    };
+    output.item(&report, format_usage)
+}
+
+fn format_usage(report: &UsageReport) -> String {
+    let mut lines: Vec<String> = report
+        .entries
+        .iter()
+        .map(|entry| {
+            format!(
+                "{:>10}  {}  [{}]",
+                format_size(entry.size),
+                entry.path.display(),
+                format_breakdown(&entry.breakdown)
+            )
+        })
+        .collect();
+
+    lines.push(String::new());
+    lines.push(format!(
+        "Total: {} ({})",
+        format_size(report.total.size),
+        format_breakdown(&report.total.breakdown)
+    ));
+    if let Some(quota) = report.quota {
+        let used = report.account_size;
+        lines.push(format!(
+            "Account: {} of {} used ({:.1}%)",
+            format_size(used),
+            format_size(quota),
+            used as f64 / quota.max(1) as f64 * 100.0
+        ));
+        if used > quota {
+            lines.push("Warning: the account is over its quota".to_string());
         }
-        _ => None,
+    }
+    lines.join("\n")
+}
+
+/// Adds up the usage of `node` and everything below it.
+fn subtree_usage(node: &Node, usage: &HashMap<String, SizeBreakdown>) -> SizeBreakdown {
+    let mut breakdown = usage.get(&node.id()).cloned().unwrap_or_default();
+    for child in node.children.values() {
+        breakdown.merge(&subtree_usage(child, usage));
+    }
+    breakdown
+}
+
+fn format_breakdown(breakdown: &BTreeMap<SubfileKind, u64>) -> String {
+    breakdown
+        .iter()
+        .filter(|(_, size)| **size > 0)
+        .map(|(kind, size)| format!("{} {}", kind, format_size(*size)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Runs `command` through the platform shell, `sh -c` or `cmd /C`.
+pub fn shell_command(command: &str) -> tokio::process::Command {
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
     };
+    let mut process = tokio::process::Command::new(shell);
+    process.arg(flag).arg(command);
+    process
+}
+
+/// Formats a byte count with binary units, e.g. `1.5 MiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+pub async fn grep(
+    client: &RmClient,
+    output: OutputFormat,
+    pattern: &str,
+    path: &Path,
+    ignore_case: bool,
+) -> Result<(), Error> {
+    // Reject a bad pattern before spending time on the index
+    let regex = compile_pattern(pattern, ignore_case)?;
+    let node = client.filesystem.find_node_by_path(path)?;
+    let documents: Vec<(PathBuf, String)> = if node.is_directory() {
+        let query = FindQuery {
+            kind: Some(NodeKind::File),
+            ..Default::default()
+        };
+        client
+            .filesystem
+            .find(path, &query)?
+            .into_iter()
+            .map(|(doc_path, doc)| (doc_path, doc.id()))
+            .collect()
+    } else {
+        vec![(path.to_path_buf(), node.id())]
+    };
+    let doc_ids: Vec<String> = documents.iter().map(|(_, id)| id.clone()).collect();
+
+    let mut index = SearchIndex::load()?;
+    client.update_search_index(&mut index, &doc_ids).await?;
+    index.save()?;
+
+    let paths: HashMap<&str, &Path> = documents
+        .iter()
+        .map(|(doc_path, id)| (id.as_str(), doc_path.as_path()))
+        .collect();
+    let records: Vec<MatchRecord> = index
+        .search(&doc_ids, &regex)
+        .into_iter()
+        .map(|found| MatchRecord {
+            path: paths[found.doc_id.as_str()].to_path_buf(),
+            id: found.doc_id,
+            page: found.page,
+            line: found.line,
+        })
+        .collect();
+    output.list(&records, |record| {
+        format!("{}:{}: {}", record.path.display(), record.page, record.line)
+    })
+}
+
+/// Expands remote glob patterns into the paths they match.
+///
+/// Paths matched by several patterns are only returned once, at their first
+/// position.
+pub fn expand_paths(client: &RmClient, patterns: &[PathBuf]) -> Result<Vec<PathBuf>, Error> {
+    let mut seen = HashSet::new();
+    let mut paths = Vec::new();
+    for pattern in patterns {
+        for path in client.filesystem.expand_glob(pattern)? {
+            if seen.insert(path.clone()) {
+                paths.push(path);
+            }
+        }
+    }
+    Ok(paths)
+}
+
+pub async fn rm(
+    client: &RmClient,
+    output: OutputFormat,
+    patterns: &[PathBuf],
+) -> Result<(), Error> {
+    let paths = expand_paths(client, patterns)?;
+    if paths.iter().any(|path| path == Path::new("/")) {
+        return Err(Error::Message(
+            "Cannot remove the root directory".to_string(),
+        ));
+    }
+
+    let documents = paths
+        .iter()
+        .map(|path| {
+            client
+                .filesystem
+                .find_node_by_path_strict(path)
+                .map(|node| &node.document)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
 
     client
-        .put_document(path, parent_id.as_deref())
+        .delete_entries(&documents)
         .await
         .map_err(Error::Rmapi)?;
 
-    let dest_display = destination.unwrap_or(Path::new("/")).display();
-    println!("Upload successful to {}", dest_display);
-    Ok(())
+    let records: Vec<ChangeRecord> = paths
+        .iter()
+        .map(|path| ChangeRecord::new("removed", path))
+        .collect();
+    output.list(&records, |record| {
+        format!("Removed {}", record.path.display())
+    })
 }
 
-pub async fn get(client: &RmClient, path: &Path, recursive: bool) -> Result<(), Error> {
-    let node = client.filesystem.find_node_by_path(path)?;
+/// Splits `put` arguments into the local sources and the remote destination.
+///
+/// An explicit `dest` takes precedence. Otherwise the last of several paths
+/// is the destination, unless it exists below `local_dir` and so is meant to
+/// be uploaded as well.
+pub fn split_destination<'a>(
+    paths: &'a [PathBuf],
+    dest: Option<&'a PathBuf>,
+    local_dir: &Path,
+) -> (&'a [PathBuf], Option<&'a PathBuf>) {
+    if dest.is_some() {
+        return (paths, dest);
+    }
+    match paths.split_last() {
+        Some((destination, sources))
+            if !sources.is_empty() && !local_dir.join(destination).exists() =>
+        {
+            (sources, Some(destination))
+        }
+        _ => (paths, None),
+    }
+}
+
+pub async fn put(
+    client: &RmClient,
+    output: OutputFormat,
+    paths: &[PathBuf],
+    destination: &Path,
+    recursive: bool,
+    converters: &ConverterRegistry,
+) -> Result<(), Error> {
+    let node = client.filesystem.find_node_by_path(destination)?;
+    if !node.is_directory() {
+        return Err(Error::Message(format!(
+            "Destination is not a directory: {}",
+            destination.display()
+        )));
+    }
+
+    // Files named on the command line are converted; inside directories
+    // only PDFs and EPUBs are picked up
+    let staging = tempfile::tempdir()?;
+    let mut originals = HashMap::new();
+    let mut sources = Vec::new();
+    let mut conversion_failures = Vec::new();
+    for (index, path) in paths.iter().enumerate() {
+        let Some(converter) = converters.find(path).filter(|_| path.is_file()) else {
+            sources.push(path.clone());
+            continue;
+        };
+        // One directory per file keeps `notes.md` and `notes.txt` apart
+        let out_dir = staging.path().join(index.to_string());
+        std::fs::create_dir(&out_dir)?;
+        match converter.convert(path, &out_dir).await {
+            Ok(converted) => {
+                originals.insert(converted.clone(), path.clone());
+                sources.push(converted);
+            }
+            Err(e) => {
+                log::error!("Failed to convert {}: {}", path.display(), e);
+                conversion_failures.push((path.clone(), rmapi::Error::Message(e.to_string())));
+            }
+        }
+    }
+
+    let mut report = client
+        .upload_paths(&sources, Some(&node.id()), recursive)
+        .await?;
+    report.failed.extend(conversion_failures);
+    for (path, _) in report.uploaded.iter_mut() {
+        if let Some(original) = originals.get(path) {
+            *path = original.clone();
+        }
+    }
+    for (path, _) in report.failed.iter_mut() {
+        if let Some(original) = originals.get(path) {
+            *path = original.clone();
+        }
+    }
+
+    let change_records = |action, entries: &[(PathBuf, PathBuf)]| -> Vec<ChangeRecord> {
+        entries
+            .iter()
+            .map(|(local, remote)| {
+                ChangeRecord::new(action, local).with_destination(&destination.join(remote))
+            })
+            .collect()
+    };
+    let summary = UploadSummary {
+        uploaded: change_records("uploaded", &report.uploaded),
+        folders: change_records("created", &report.folders),
+        skipped: report
+            .skipped
+            .iter()
+            .map(|(path, reason)| SkippedRecord {
+                path: path.clone(),
+                reason: reason.clone(),
+            })
+            .collect(),
+        failed: failure_records(&report.failed),
+    };
+    output.item(&summary, |summary| {
+        let mut lines: Vec<String> = summary
+            .uploaded
+            .iter()
+            .map(|record| {
+                format!(
+                    "Uploaded {} to {}",
+                    record.path.display(),
+                    record
+                        .destination
+                        .as_deref()
+                        .unwrap_or(destination)
+                        .display()
+                )
+            })
+            .collect();
+        lines.extend(
+            summary
+                .skipped
+                .iter()
+                .map(|skipped| format!("Skipped {}: {}", skipped.path.display(), skipped.reason)),
+        );
+        lines.extend(format_failures(&summary.failed));
+        lines.push(format!(
+            "Upload complete: {} documents in {} new folders, {} skipped",
+            summary.uploaded.len(),
+            summary.folders.len(),
+            summary.skipped.len()
+        ));
+        lines.join("\n")
+    })?;
+
+    if summary.failed.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Message(format!(
+            "{} entries could not be uploaded",
+            summary.failed.len()
+        )))
+    }
+}
+
+pub async fn replace(
+    client: &RmClient,
+    output: OutputFormat,
+    path: &Path,
+    remote: &Path,
+) -> Result<(), Error> {
+    let node = client.filesystem.find_node_by_path_strict(remote)?;
+    if node.is_directory() {
+        return Err(Error::Message(format!(
+            "Cannot replace a directory: {}",
+            remote.display()
+        )));
+    }
+
+    let report = client.replace_document(&node.id(), path).await?;
+
+    let record = ReplaceRecord {
+        path: path.to_path_buf(),
+        destination: remote.to_path_buf(),
+        pages_before: report.pages_before,
+        pages_after: report.pages_after,
+        removed_pages: report.removed_pages,
+    };
+    output.item(&record, |record| {
+        let mut text = format!(
+            "Replaced {} ({} -> {} pages)",
+            record.destination.display(),
+            record.pages_before,
+            record.pages_after
+        );
+        if !record.removed_pages.is_empty() {
+            text.push_str(&format!(
+                ", removed {} pages past the end of the new PDF with their annotations",
+                record.removed_pages.len()
+            ));
+        }
+        text
+    })
+}
+
+pub async fn put_html(
+    client: &RmClient,
+    output: OutputFormat,
+    path: &Path,
+    destination: &Path,
+    title: Option<&str>,
+) -> Result<(), Error> {
+    let node = client.filesystem.find_node_by_path(destination)?;
+    if !node.is_directory() {
+        return Err(Error::Message(format!(
+            "Destination is not a directory: {}",
+            destination.display()
+        )));
+    }
+    let parent_id = node.id();
+
+    let epub = package_html(path, title)?;
+    for src in &epub.skipped_images {
+        log::warn!("Left out image that is not a local file: {}", src);
+    }
+    // The file name only has to be valid locally, the document gets the
+    // title as its name
+    let staging = tempfile::tempdir()?;
+    let epub_path = staging
+        .path()
+        .join(format!("{}.epub", sanitize_file_name(&epub.title)));
+    tokio::fs::write(&epub_path, &epub.data).await?;
+    let options = UploadOptions {
+        visible_name: Some(epub.title.clone()),
+        ..Default::default()
+    };
     client
-        .download_entry(node, std::path::PathBuf::from("."), recursive)
-        .map_err(Error::Rmapi)?
-        .await
-        .map_err(Error::Rmapi)?;
+        .put_document_with(&epub_path, Some(&parent_id), &options)
+        .await?;
+
+    let record =
+        ChangeRecord::new("uploaded", path).with_destination(&destination.join(&epub.title));
+    output.item(&record, |record| {
+        format!(
+            "Uploaded {} to {} with {} images",
+            record.path.display(),
+            record
+                .destination
+                .as_deref()
+                .unwrap_or(destination)
+                .display(),
+            epub.images
+        )
+    })
+}
+
+pub async fn get(
+    client: &RmClient,
+    output: OutputFormat,
+    patterns: &[PathBuf],
+    destination: &Path,
+    options: DownloadOptions,
+) -> Result<(), Error> {
+    if !destination.is_dir() {
+        return Err(Error::Message(format!(
+            "Not a local directory: {}",
+            destination.display()
+        )));
+    }
+
+    let mut records = Vec::new();
+    for path in expand_paths(client, patterns)? {
+        let node = client.filesystem.find_node_by_path(&path)?;
+        let files = client
+            .download_entry(node, destination.to_path_buf(), options)
+            .map_err(Error::Rmapi)?
+            .await
+            .map_err(Error::Rmapi)?;
+        let parent = path.parent().unwrap_or(Path::new("/"));
+        for file in files {
+            let action = if file.skipped {
+                "skipped"
+            } else {
+                "downloaded"
+            };
+            records.push(
+                ChangeRecord::new(action, &parent.join(&file.remote_path))
+                    .with_destination(&file.local_path),
+            );
+        }
+    }
+
+    if !output.is_table() {
+        return output.records(&records);
+    }
+    for record in records.iter().filter(|record| record.action == "skipped") {
+        println!(
+            "Skipped {}: {} already exists",
+            record.path.display(),
+            record
+                .destination
+                .as_deref()
+                .unwrap_or(Path::new(""))
+                .display()
+        );
+    }
     println!("Download complete");
     Ok(())
 }
@@ -86,12 +827,43 @@ pub fn cd(client: &RmClient, path: &Path) -> Result<(), Error> {
     Ok(())
 }
 
-pub async fn mv(client: &RmClient, path: &Path, destination: &Path) -> Result<(), Error> {
-    let src_node = client.filesystem.find_node_by_path(path)?;
+pub async fn mv(
+    client: &RmClient,
+    output: OutputFormat,
+    sources: &[PathBuf],
+    destination: &Path,
+) -> Result<(), Error> {
+    let paths = expand_paths(client, sources)?;
+    if paths.len() > 1 {
+        let is_directory = client
+            .filesystem
+            .find_node_by_path_strict(destination)
+            .map(|node| node.is_directory())
+            .unwrap_or(false);
+        if !is_directory {
+            return Err(Error::Message(format!(
+                "Target is not a directory: {}",
+                destination.display()
+            )));
+        }
+    }
+
+    let mut records = Vec::new();
+    for path in &paths {
+        mv_one(client, path, destination).await?;
+        records.push(ChangeRecord::new("moved", path).with_destination(destination));
+    }
+
+    // Moves are silent in table mode
+    output.records(&records)
+}
+
+async fn mv_one(client: &RmClient, path: &Path, destination: &Path) -> Result<(), Error> {
+    let src_node = client.filesystem.find_node_by_path_strict(path)?;
     let src_id = src_node.id().to_string();
 
     // Check if destination exists
-    match client.filesystem.find_node_by_path(destination) {
+    match client.filesystem.find_node_by_path_strict(destination) {
         Ok(dest_node) => {
             if dest_node.is_directory() {
                 // Move into directory
@@ -104,12 +876,16 @@ pub async fn mv(client: &RmClient, path: &Path, destination: &Path) -> Result<()
                 return Err(Error::Message("Destination already exists".to_string()));
             }
         }
+        // The destination exists but the name is ambiguous
+        Err(e) if client.filesystem.find_node_by_path(destination).is_ok() => {
+            return Err(Error::Rmapi(e));
+        }
         Err(_) => {
             // Destination does not exist, treat as rename/move-to-new-name
             // Ensure parent exists
             let parent = destination.parent().unwrap_or(Path::new("/"));
 
-            let parent_node = client.filesystem.find_node_by_path(parent)?;
+            let parent_node = client.filesystem.find_node_by_path_strict(parent)?;
             if !parent_node.is_directory() {
                 return Err(Error::Message(
                     "Destination parent is not a directory".to_string(),
@@ -131,3 +907,174 @@ pub async fn mv(client: &RmClient, path: &Path, destination: &Path) -> Result<()
 
     Ok(())
 }
+
+pub async fn backup(
+    client: &RmClient,
+    output: OutputFormat,
+    dir: &Path,
+    prune: bool,
+) -> Result<(), Error> {
+    let policy = if prune {
+        RemovedPolicy::Delete
+    } else {
+        RemovedPolicy::Archive
+    };
+    let report = client.backup(dir, policy).await?;
+
+    let summary = BackupSummary {
+        downloaded: report.downloaded,
+        moved: report
+            .moved
+            .iter()
+            .map(|(from, to)| ChangeRecord::new("moved", from).with_destination(to))
+            .collect(),
+        unchanged: report.unchanged,
+        removed: report.removed,
+        failed: failure_records(&report.failed),
+    };
+    output.item(&summary, |summary| format_backup(summary, prune))?;
+
+    if summary.failed.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Message(format!(
+            "{} document(s) could not be backed up",
+            summary.failed.len()
+        )))
+    }
+}
+
+fn format_backup(summary: &BackupSummary, prune: bool) -> String {
+    let mut lines = Vec::new();
+    for path in &summary.downloaded {
+        lines.push(format!("Downloaded {}", path.display()));
+    }
+    for moved in &summary.moved {
+        let to = moved.destination.as_deref().unwrap_or(Path::new(""));
+        lines.push(format!(
+            "Moved {} -> {}",
+            moved.path.display(),
+            to.display()
+        ));
+    }
+    for path in &summary.removed {
+        let action = if prune { "Deleted" } else { "Archived" };
+        lines.push(format!("{} {}", action, path.display()));
+    }
+    lines.extend(format_failures(&summary.failed));
+    lines.push(format!(
+        "Backup complete: {} downloaded, {} moved, {} unchanged, {} removed",
+        summary.downloaded.len(),
+        summary.moved.len(),
+        summary.unchanged,
+        summary.removed.len()
+    ));
+    lines.join("\n")
+}
+
+fn failure_records(failed: &[(PathBuf, rmapi::Error)]) -> Vec<FailureRecord> {
+    failed
+        .iter()
+        .map(|(path, err)| FailureRecord {
+            path: path.clone(),
+            error: err.to_string(),
+        })
+        .collect()
+}
+
+fn format_failures(failed: &[FailureRecord]) -> Vec<String> {
+    failed
+        .iter()
+        .map(|failure| format!("Failed {}: {}", failure.path.display(), failure.error))
+        .collect()
+}
+
+pub async fn restore_backup(
+    client: &RmClient,
+    output: OutputFormat,
+    dir: &Path,
+    only: Option<&Path>,
+    into: Option<&Path>,
+) -> Result<(), Error> {
+    let into_id = match into {
+        Some(into) => {
+            let node = client.filesystem.find_node_by_path(into)?;
+            if !node.is_directory() {
+                return Err(Error::Message(format!(
+                    "Destination is not a directory: {}",
+                    into.display()
+                )));
+            }
+            Some(node.id())
+        }
+        None => None,
+    };
+
+    let report = client.restore_backup(dir, only, into_id.as_deref()).await?;
+
+    let summary = RestoreSummary {
+        restored: report.restored,
+        skipped: report.skipped,
+        failed: failure_records(&report.failed),
+    };
+    output.item(&summary, |summary| {
+        let mut lines: Vec<String> = summary
+            .restored
+            .iter()
+            .map(|path| format!("Restored {}", path.display()))
+            .collect();
+        lines.extend(format_failures(&summary.failed));
+        lines.push(format!(
+            "Restore complete: {} restored, {} already present",
+            summary.restored.len(),
+            summary.skipped
+        ));
+        lines.join("\n")
+    })?;
+
+    if summary.failed.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Message(format!(
+            "{} entries could not be restored",
+            summary.failed.len()
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_destination() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.pdf"), b"").unwrap();
+        std::fs::write(dir.path().join("b.pdf"), b"").unwrap();
+        let path = |p: &str| PathBuf::from(p);
+
+        let both = [path("a.pdf"), path("b.pdf")];
+        assert_eq!(
+            split_destination(&both, None, dir.path()),
+            (&both[..], None)
+        );
+
+        let with_folder = [path("a.pdf"), path("Papers")];
+        assert_eq!(
+            split_destination(&with_folder, None, dir.path()),
+            (&with_folder[..1], Some(&with_folder[1]))
+        );
+
+        let single = [path("Papers")];
+        assert_eq!(
+            split_destination(&single, None, dir.path()),
+            (&single[..], None)
+        );
+
+        let dest = path("b.pdf");
+        assert_eq!(
+            split_destination(&both, Some(&dest), dir.path()),
+            (&both[..], Some(&dest))
+        );
+    }
+}
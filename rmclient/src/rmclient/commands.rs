@@ -1,4 +1,8 @@
-use clap::Subcommand;
+use chrono::{DateTime, NaiveDate, Utc};
+use clap::{Args, Subcommand, ValueEnum};
+use rmapi::client::{ConflictPolicy, DownloadOptions};
+use rmapi::filesystem::FindQuery;
+use rmapi::objects::NodeKind;
 use std::path::PathBuf;
 
 #[derive(Subcommand, Debug)]
@@ -12,34 +16,329 @@ pub enum Commands {
     Ls {
         /// Optional path to list
         path: Option<PathBuf>,
+        #[command(flatten)]
+        options: LsArgs,
     },
-    /// Start interactive shell
-    Shell,
-    /// Upload a file to the reMarkable Cloud
-    Put {
-        /// Path to the file to upload
+    /// Show the folder hierarchy with document counts and sizes
+    Tree {
+        /// Directory to show (defaults to root)
+        path: Option<PathBuf>,
+        #[command(flatten)]
+        options: TreeArgs,
+    },
+    /// Show storage used per document and folder, by kind of file
+    Du {
+        /// File or directory to report on (defaults to root)
+        path: Option<PathBuf>,
+        #[command(flatten)]
+        options: DuArgs,
+    },
+    /// Show the metadata and stored files of a document or folder
+    Stat {
+        /// Path of the document or folder
         path: PathBuf,
-        /// Optional target directory (defaults to root)
+        /// Print the raw information as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Start interactive shell, or run shell commands from a script or piped stdin
+    Shell {
+        /// File with one shell command per line; stops at the first failing command
+        #[arg(long)]
+        script: Option<PathBuf>,
+    },
+    /// Upload files or directories to the reMarkable Cloud
+    Put {
+        /// Local files to upload, followed by the target directory when more
+        /// than one path is given and the last one does not exist locally
+        /// (defaults to root)
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// Target directory, so every path is uploaded
+        #[arg(short, long, value_name = "REMOTE", conflicts_with = "replace")]
+        dest: Option<PathBuf>,
+        /// Upload directories with their contents, recreating them as folders
+        #[arg(short, long)]
+        recursive: bool,
+        /// Replace the PDF of this existing document, keeping its annotations
+        #[arg(long, value_name = "REMOTE", conflicts_with = "recursive")]
+        replace: Option<PathBuf>,
+    },
+    /// Package an HTML file and its local images as an EPUB and upload it
+    #[command(name = "put-html")]
+    PutHtml {
+        /// Local HTML file
+        file: PathBuf,
+        /// Target directory (defaults to root)
         destination: Option<PathBuf>,
+        /// Document title, instead of the one of the page
+        #[arg(long)]
+        title: Option<String>,
     },
-    /// Remove a file or directory
+    /// Remove files or directories (supports wildcards)
     Rm {
-        /// Path of the file to remove
-        path: PathBuf,
+        /// Paths or glob patterns of the files to remove
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
     },
-    /// Download a file or directory
+    /// Download files or directories (supports wildcards)
     Get {
-        /// Path of the file/directory to download
-        path: PathBuf,
-        /// Recursive download
-        #[arg(short, long)]
-        recursive: bool,
+        /// Paths or glob patterns of the files/directories to download
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        #[command(flatten)]
+        options: GetArgs,
     },
-    /// Move a file or directory
+    /// Move or rename files or directories (supports wildcards)
     Mv {
-        /// Path of the file/directory to move
-        path: PathBuf,
-        /// Destination path
+        /// Paths or glob patterns of the files/directories to move
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// Destination path (has to be a directory when moving several entries)
         destination: PathBuf,
     },
+    /// Mirror the whole account into a local directory
+    Backup {
+        /// Local directory that holds the backup
+        dir: PathBuf,
+        /// Delete local copies of removed documents instead of archiving them
+        #[arg(long)]
+        prune: bool,
+    },
+    /// Restore folders and documents from a local backup
+    RestoreBackup {
+        /// Local directory that holds the backup
+        dir: PathBuf,
+        /// Only restore this folder or document of the backup, given as a
+        /// path inside the backup directory
+        #[arg(long, value_name = "PATH")]
+        only: Option<PathBuf>,
+        /// Remote folder to restore into (defaults to root)
+        #[arg(long)]
+        into: Option<PathBuf>,
+    },
+    /// Search the file tree by name, type, date, pinned state and tags
+    Find {
+        /// Directory to search in (defaults to root)
+        root: Option<PathBuf>,
+        #[command(flatten)]
+        filters: FindArgs,
+    },
+    /// Search the text of documents (PDF/EPUB text, typed text and highlights)
+    Grep {
+        /// Regular expression to search for
+        pattern: String,
+        /// File or directory to search in (defaults to root)
+        path: Option<PathBuf>,
+        /// Ignore case when matching
+        #[arg(short, long)]
+        ignore_case: bool,
+    },
+    /// Upload PDFs and EPUBs dropped into a local directory until interrupted
+    Watch {
+        /// Local directory to watch
+        local_dir: PathBuf,
+        /// Remote directory to upload into
+        remote_dir: PathBuf,
+        #[command(flatten)]
+        options: WatchArgs,
+    },
+    /// Print changes made in the cloud, e.g. on the tablet, as they happen
+    #[command(name = "watch-remote")]
+    WatchRemote {
+        /// Seconds between two checks of the cloud
+        #[arg(long, value_name = "SECONDS", default_value_t = 30)]
+        interval: u64,
+        /// Shell command to run for every change, with the change in the
+        /// RMAPI_EVENT, RMAPI_ID, RMAPI_PATH, RMAPI_PREVIOUS_PATH and
+        /// RMAPI_TYPE environment variables
+        #[arg(long, value_name = "COMMAND")]
+        exec: Option<String>,
+    },
+    /// Print a shell completion script
+    Completions {
+        /// Shell to generate the script for
+        shell: clap_complete::Shell,
+    },
+    /// Print the cached remote paths completing a partial path (used by completion scripts)
+    #[command(name = "__complete", hide = true)]
+    Complete {
+        /// Partially typed remote path
+        #[arg(default_value = "", allow_hyphen_values = true)]
+        partial: String,
+    },
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct LsArgs {
+    /// Long format: type, pinned, size, pages, modified time, ID and tags
+    #[arg(short, long)]
+    pub long: bool,
+    /// List subdirectories recursively
+    #[arg(short = 'R', long)]
+    pub recursive: bool,
+    /// Sort entries by this key
+    #[arg(long, value_enum, default_value_t = LsSort::Name)]
+    pub sort: LsSort,
+    /// Reverse the sort order
+    #[arg(short, long)]
+    pub reverse: bool,
+    /// Only list directories (d) or files (f)
+    #[arg(long = "type", value_enum)]
+    pub node_type: Option<NodeType>,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LsSort {
+    /// Directories first, then by name
+    Name,
+    /// Most recently modified first
+    Mtime,
+    /// Largest first
+    Size,
+    /// Most recently created first
+    Created,
+}
+
+#[derive(Args, Debug, Clone)]
+#[command(group = clap::ArgGroup::new("conflict").multiple(false))]
+pub struct GetArgs {
+    /// Recursive download
+    #[arg(short, long)]
+    pub recursive: bool,
+    /// Local directory to download into (defaults to the current directory)
+    #[arg(short = 'o', long = "dest")]
+    pub dest: Option<PathBuf>,
+    /// Replace local files that already exist
+    #[arg(long, group = "conflict")]
+    pub overwrite: bool,
+    /// Keep local files that already exist and skip those documents
+    #[arg(long, group = "conflict")]
+    pub skip: bool,
+    /// Download to a free name like "Notes (1).pdf" when a file exists (default)
+    #[arg(long, group = "conflict")]
+    pub rename: bool,
+    /// Use each document's last modification time as the local file's mtime
+    #[arg(short, long)]
+    pub preserve_mtime: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct WatchArgs {
+    /// Move uploaded files into a "done" folder inside the watched directory
+    #[arg(long)]
+    pub done: bool,
+    /// Seconds a file has to stay unchanged before it is uploaded
+    #[arg(long, value_name = "SECONDS", default_value_t = 2.0)]
+    pub debounce: f64,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct TreeArgs {
+    /// Only descend this many levels
+    #[arg(short = 'L', long)]
+    pub depth: Option<usize>,
+    /// Show aggregated sizes
+    #[arg(short, long)]
+    pub size: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct DuArgs {
+    /// Order of the listed entries
+    #[arg(long, value_enum, default_value_t = DuSort::Path)]
+    pub sort: DuSort,
+    /// Account quota to compare the total against, e.g. 8G or 512MiB
+    #[arg(long, env = "RMAPI_QUOTA", value_parser = parse_quota)]
+    pub quota: Option<u64>,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuSort {
+    /// By path
+    Path,
+    /// Largest first
+    Size,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct FindArgs {
+    /// Glob pattern the name has to match
+    #[arg(long)]
+    pub name: Option<String>,
+    /// Only match directories (d) or files (f)
+    #[arg(long = "type", value_enum)]
+    pub node_type: Option<NodeType>,
+    /// Only match entries modified after this date (YYYY-MM-DD or RFC 3339)
+    #[arg(long, value_parser = parse_datetime)]
+    pub modified_after: Option<DateTime<Utc>>,
+    /// Only match entries modified before this date (YYYY-MM-DD or RFC 3339)
+    #[arg(long, value_parser = parse_datetime)]
+    pub modified_before: Option<DateTime<Utc>>,
+    /// Only match pinned entries
+    #[arg(long)]
+    pub pinned: bool,
+    /// Only match entries carrying this tag (can be repeated)
+    #[arg(long = "tag")]
+    pub tags: Vec<String>,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum NodeType {
+    /// Directory
+    D,
+    /// File
+    F,
+}
+
+impl From<NodeType> for NodeKind {
+    fn from(node_type: NodeType) -> Self {
+        match node_type {
+            NodeType::D => NodeKind::Directory,
+            NodeType::F => NodeKind::File,
+        }
+    }
+}
+
+impl From<FindArgs> for FindQuery {
+    fn from(args: FindArgs) -> Self {
+        FindQuery {
+            name: args.name,
+            kind: args.node_type.map(NodeKind::from),
+            modified_after: args.modified_after,
+            modified_before: args.modified_before,
+            pinned: args.pinned,
+            tags: args.tags,
+        }
+    }
+}
+
+impl From<&GetArgs> for DownloadOptions {
+    fn from(args: &GetArgs) -> Self {
+        let conflict = if args.overwrite {
+            ConflictPolicy::Overwrite
+        } else if args.skip {
+            ConflictPolicy::Skip
+        } else {
+            ConflictPolicy::Rename
+        };
+        DownloadOptions {
+            recursive: args.recursive,
+            conflict,
+            preserve_mtime: args.preserve_mtime,
+        }
+    }
+}
+
+fn parse_quota(value: &str) -> Result<u64, String> {
+    rmapi::usage::parse_size(value).map_err(|e| e.to_string())
+}
+
+fn parse_datetime(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Ok(datetime.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|date| date.and_hms_opt(0, 0, 0).unwrap().and_utc())
+        .map_err(|_| format!("Invalid date: {} (expected YYYY-MM-DD or RFC 3339)", value))
 }
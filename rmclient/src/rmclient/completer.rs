@@ -0,0 +1,224 @@
+use rmapi::filesystem::FileSystem;
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+use std::path::{Path, PathBuf};
+
+/// Tab completion for the interactive shell.
+///
+/// Completes command names, remote paths from a snapshot of the file tree
+/// and, for the arguments of `put`, `lcd` and `lls`, local paths.
+pub struct ShellHelper {
+    commands: Vec<String>,
+    filesystem: FileSystem,
+    current_path: PathBuf,
+    local_path: PathBuf,
+}
+
+impl ShellHelper {
+    pub fn new(commands: Vec<String>) -> Self {
+        ShellHelper {
+            commands,
+            filesystem: FileSystem::new(),
+            current_path: PathBuf::from("/"),
+            local_path: PathBuf::from("."),
+        }
+    }
+
+    /// Brings the snapshot used for remote paths up to date.
+    pub fn update(&mut self, filesystem: &FileSystem, current_path: &Path, local_path: &Path) {
+        // The tree only changes when the root hash does, so skip the copy otherwise
+        if self.filesystem.current_hash != filesystem.current_hash {
+            self.filesystem = filesystem.clone();
+        }
+        self.current_path = current_path.to_path_buf();
+        self.local_path = local_path.to_path_buf();
+    }
+
+    /// Local paths completing `partial`, resolved against the local directory.
+    fn complete_local(&self, partial: &str) -> Vec<String> {
+        let (dir_part, prefix) = match partial.rfind('/') {
+            Some(idx) => partial.split_at(idx + 1),
+            None => ("", partial),
+        };
+        let Ok(entries) = std::fs::read_dir(self.local_path.join(dir_part)) else {
+            return Vec::new();
+        };
+
+        let mut candidates: Vec<String> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                // Hidden files only show up once a dot is typed
+                if !name.starts_with(prefix) || (name.starts_with('.') && prefix.is_empty()) {
+                    return None;
+                }
+                let is_dir = entry.path().is_dir();
+                Some(format!(
+                    "{}{}{}",
+                    dir_part,
+                    name,
+                    if is_dir { "/" } else { "" }
+                ))
+            })
+            .collect();
+        candidates.sort();
+        candidates
+    }
+}
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        // There is always a last, possibly empty, word being completed
+        let words = split_words(&line[..pos]);
+        let (start, partial) = words[words.len() - 1].clone();
+
+        if words.len() == 1 {
+            let candidates = self
+                .commands
+                .iter()
+                .filter(|command| command.starts_with(&partial))
+                .map(|command| Pair {
+                    display: command.clone(),
+                    replacement: format!("{} ", command),
+                })
+                .collect();
+            return Ok((start, candidates));
+        }
+
+        let is_local = match words[0].1.as_str() {
+            // The destination of put is remote, and those are usually absolute
+            "put" => !partial.starts_with('/'),
+            "lcd" | "lls" => true,
+            _ => false,
+        };
+        let candidates = if is_local {
+            self.complete_local(&partial)
+        } else {
+            self.filesystem.complete_path(&partial, &self.current_path)
+        };
+
+        let candidates = candidates
+            .into_iter()
+            .map(|candidate| {
+                let display = candidate
+                    .trim_end_matches('/')
+                    .rsplit('/')
+                    .next()
+                    .unwrap_or_default()
+                    .to_string();
+                let suffix = if candidate.ends_with('/') { "/" } else { "" };
+                let replacement = match shlex::try_quote(&candidate) {
+                    Ok(quoted) if candidate.ends_with('/') => quoted.into_owned(),
+                    Ok(quoted) => format!("{} ", quoted),
+                    Err(_) => candidate.clone(),
+                };
+                Pair {
+                    display: format!("{}{}", display, suffix),
+                    replacement,
+                }
+            })
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+/// Splits a partial command line into words the way shlex would, returning
+/// the byte offset where each word starts and its unquoted text.
+///
+/// Unlike `shlex::split` this accepts an unterminated quote in the last word,
+/// and it yields an empty last word when the line ends in whitespace.
+fn split_words(line: &str) -> Vec<(usize, String)> {
+    let mut words = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+    let mut quote = None;
+    let mut chars = line.char_indices();
+
+    while let Some((idx, c)) = chars.next() {
+        if quote.is_none() && c.is_whitespace() {
+            if let Some(word) = current.take() {
+                words.push(word);
+            }
+            continue;
+        }
+        let word = &mut current.get_or_insert_with(|| (idx, String::new())).1;
+        match (quote, c) {
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None | Some('"'), '\\') => {
+                if let Some((_, escaped)) = chars.next() {
+                    word.push(escaped);
+                }
+            }
+            (_, c) => word.push(c),
+        }
+    }
+
+    words.push(current.unwrap_or((line.len(), String::new())));
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_words(line: &str, expected: &[(usize, &str)]) {
+        let words = split_words(line);
+        let words: Vec<(usize, &str)> = words
+            .iter()
+            .map(|(start, word)| (*start, word.as_str()))
+            .collect();
+        assert_eq!(words, expected, "{:?}", line);
+    }
+
+    #[test]
+    fn test_split_words() {
+        assert_words("ls /Papers", &[(0, "ls"), (3, "/Papers")]);
+        assert_words("", &[(0, "")]);
+    }
+
+    #[test]
+    fn test_split_words_quoted() {
+        assert_words(
+            "get 'My Notes' \"Other \\\"one\\\"\"",
+            &[(0, "get"), (4, "My Notes"), (15, "Other \"one\"")],
+        );
+        // The word being completed may still have an open quote
+        assert_words("cd \"Book Dr", &[(0, "cd"), (3, "Book Dr")]);
+        // Backslashes are literal inside single quotes
+        assert_words(r"ls 'a\b'", &[(0, "ls"), (3, r"a\b")]);
+    }
+
+    #[test]
+    fn test_split_words_escaped_space() {
+        assert_words(
+            r"mv My\ Notes dest",
+            &[(0, "mv"), (3, "My Notes"), (13, "dest")],
+        );
+    }
+
+    #[test]
+    fn test_split_words_trailing_space() {
+        assert_words("ls ", &[(0, "ls"), (3, "")]);
+        assert_words("put a.pdf  ", &[(0, "put"), (4, "a.pdf"), (11, "")]);
+    }
+}
@@ -0,0 +1,86 @@
+use clap_complete::Shell;
+use rmapi::filesystem::FileSystem;
+use std::io::Write;
+use std::path::Path;
+
+use crate::rmclient::error::Error;
+
+/// Name of the hidden command the generated scripts call for remote paths
+pub const COMPLETE_COMMAND: &str = "__complete";
+
+/// Prints the completion script for `shell`.
+///
+/// On top of what clap generates, the bash, zsh and fish scripts complete
+/// words starting with `/` as remote paths by calling `__complete`. The
+/// candidates are escaped, so names with spaces stay a single word.
+pub fn print_completions(shell: Shell, command: &mut clap::Command) -> Result<(), Error> {
+    let bin_name = command.get_name().to_string();
+    let mut stdout = std::io::stdout();
+    clap_complete::generate(shell, command, &bin_name, &mut stdout);
+
+    let remote = match shell {
+        Shell::Bash => format!(
+            r#"
+_{bin}_remote() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}"
+    if [[ "$cur" == /* ]]; then
+        # Undo the escapes of an earlier completion, and escape the candidates
+        # so names with spaces or metacharacters stay one word
+        local candidate
+        COMPREPLY=()
+        while IFS= read -r candidate; do
+            COMPREPLY+=( "$(printf '%q' "$candidate")" )
+        done < <({bin} {complete} "${{cur//\\/}}" 2>/dev/null)
+        compopt -o nospace 2>/dev/null
+    else
+        _{bin} "$@"
+    fi
+}}
+complete -F _{bin}_remote -o bashdefault -o default {bin}
+"#,
+            bin = bin_name,
+            complete = COMPLETE_COMMAND
+        ),
+        Shell::Zsh => format!(
+            r#"
+_{bin}_remote() {{
+    if [[ "$PREFIX" == /* ]]; then
+        local -a remote
+        remote=("${{(@f)$({bin} {complete} "${{(Q)PREFIX}}" 2>/dev/null)}}")
+        # compadd quotes the names itself
+        compadd -S '' -- $remote
+    else
+        _{bin} "$@"
+    fi
+}}
+compdef _{bin}_remote {bin}
+"#,
+            bin = bin_name,
+            complete = COMPLETE_COMMAND
+        ),
+        Shell::Fish => format!(
+            r#"
+complete -c {bin} -n 'string match -q -- "/*" (commandline -ct)' -f -a '({bin} {complete} (commandline -ct | string unescape))'
+"#,
+            bin = bin_name,
+            complete = COMPLETE_COMMAND
+        ),
+        _ => String::new(),
+    };
+    stdout.write_all(remote.as_bytes())?;
+    Ok(())
+}
+
+/// Prints the remote paths that complete `partial`, one per line.
+///
+/// Only the cached file tree is read, so this never touches the network and
+/// prints nothing when there is no cache yet.
+pub fn complete_remote(partial: &str) -> Result<(), Error> {
+    let Ok(filesystem) = FileSystem::load_cache() else {
+        return Ok(());
+    };
+    for candidate in filesystem.complete_path(partial, Path::new("/")) {
+        println!("{}", candidate);
+    }
+    Ok(())
+}
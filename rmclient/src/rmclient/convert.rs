@@ -0,0 +1,554 @@
+use image::codecs::jpeg::JpegDecoder;
+use image::{ColorType, DynamicImage, ImageDecoder, ImageFormat};
+use lopdf::content::{Content, Operation};
+use lopdf::{dictionary, Document, Object, ObjectId, Stream};
+use rmapi::epub::package_html;
+use rmapi::filesystem::sanitize_file_name;
+use rmapi::upload::is_supported;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use crate::rmclient::actions::shell_command;
+use crate::rmclient::error::Error;
+
+/// A4 in PDF points
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 50.0;
+const FONT_SIZE: f32 = 10.0;
+const LINE_HEIGHT: f32 = 12.0;
+/// Courier glyphs are 0.6 em wide
+const CHARS_PER_LINE: usize = ((PAGE_WIDTH - 2.0 * MARGIN) / (FONT_SIZE * 0.6)) as usize;
+const LINES_PER_PAGE: usize = ((PAGE_HEIGHT - 2.0 * MARGIN) / LINE_HEIGHT) as usize;
+const TAB_WIDTH: usize = 4;
+
+/// External converters configured by the user, keyed by file extension
+pub fn default_converters_path() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join("rmapi/converters.json")
+}
+
+/// Turns a local file the tablet cannot show into one it can.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Converter {
+    /// Plain text typeset in Courier on A4 pages
+    Text,
+    /// A PNG or JPEG scaled onto an A4 page
+    Image,
+    /// An HTML page packaged as an EPUB with its local images
+    Html,
+    /// A shell command with `{input}` and `{output}` placeholders that
+    /// writes a file with the `output` extension
+    External { command: String, output: String },
+}
+
+impl Converter {
+    /// Converts `path` into `out_dir`, keeping the file stem so the document
+    /// gets a matching name. HTML articles are named after their title
+    /// instead, as with `put-html`. Returns the converted file.
+    pub async fn convert(&self, path: &Path, out_dir: &Path) -> Result<PathBuf, Error> {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        match self {
+            Converter::Text => {
+                let data = tokio::fs::read(path).await?;
+                let target = out_dir.join(format!("{}.pdf", stem));
+                tokio::fs::write(&target, text_to_pdf(&String::from_utf8_lossy(&data))?).await?;
+                Ok(target)
+            }
+            Converter::Image => {
+                let data = tokio::fs::read(path).await?;
+                let target = out_dir.join(format!("{}.pdf", stem));
+                tokio::fs::write(&target, image_to_pdf(&data)?).await?;
+                Ok(target)
+            }
+            Converter::Html => {
+                let epub = package_html(path, None)?;
+                let target = out_dir.join(format!("{}.epub", sanitize_file_name(&epub.title)));
+                tokio::fs::write(&target, epub.data).await?;
+                Ok(target)
+            }
+            Converter::External { command, output } => {
+                let target = out_dir.join(format!("{}.{}", stem, output));
+                run_external(command, path, &target).await?;
+                Ok(target)
+            }
+        }
+    }
+}
+
+/// Entry of the converters file: a command writing a PDF, or a command
+/// with the extension of what it writes, e.g. `"epub"`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ConverterConfig {
+    Command(String),
+    Full {
+        command: String,
+        #[serde(default = "default_output")]
+        output: String,
+    },
+}
+
+fn default_output() -> String {
+    "pdf".to_string()
+}
+
+/// Maps file extensions to the converter that prepares them for upload.
+#[derive(Debug, Clone)]
+pub struct ConverterRegistry {
+    converters: BTreeMap<String, Converter>,
+}
+
+impl Default for ConverterRegistry {
+    /// The built-in converters for text, images and HTML.
+    fn default() -> Self {
+        let mut converters = BTreeMap::new();
+        for ext in ["txt", "text", "md", "markdown"] {
+            converters.insert(ext.to_string(), Converter::Text);
+        }
+        for ext in ["png", "jpg", "jpeg"] {
+            converters.insert(ext.to_string(), Converter::Image);
+        }
+        for ext in ["html", "htm", "xhtml"] {
+            converters.insert(ext.to_string(), Converter::Html);
+        }
+        ConverterRegistry { converters }
+    }
+}
+
+impl ConverterRegistry {
+    /// The built-in converters, overridden and extended by the commands in
+    /// `path` if it exists, e.g.
+    /// `{"md": "pandoc {input} -o {output}", "html": {"command": "...", "output": "epub"}}`.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let mut registry = Self::default();
+        if !path.exists() {
+            return Ok(registry);
+        }
+        let data = std::fs::read_to_string(path)?;
+        let config: BTreeMap<String, ConverterConfig> = serde_json::from_str(&data)
+            .map_err(|e| Error::Message(format!("Invalid {}: {}", path.display(), e)))?;
+        for (ext, entry) in config {
+            let (command, output) = match entry {
+                ConverterConfig::Command(command) => (command, default_output()),
+                ConverterConfig::Full { command, output } => (command, output),
+            };
+            let output = output.trim_start_matches('.').to_ascii_lowercase();
+            if !is_supported(Path::new("converted").with_extension(&output).as_path()) {
+                return Err(Error::Message(format!(
+                    "Invalid {}: converter for .{} writes unsupported .{} files",
+                    path.display(),
+                    ext,
+                    output
+                )));
+            }
+            registry.converters.insert(
+                ext.trim_start_matches('.').to_ascii_lowercase(),
+                Converter::External { command, output },
+            );
+        }
+        Ok(registry)
+    }
+
+    /// The converter for `path`, `None` for files that are uploaded as they
+    /// are or cannot be uploaded at all.
+    pub fn find(&self, path: &Path) -> Option<&Converter> {
+        if is_supported(path) {
+            return None;
+        }
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        self.converters.get(&ext)
+    }
+}
+
+async fn run_external(command: &str, input: &Path, output: &Path) -> Result<(), Error> {
+    let quote = |path: &Path| -> Result<String, Error> {
+        let path = path.to_string_lossy();
+        shlex::try_quote(&path)
+            .map(|quoted| quoted.into_owned())
+            .map_err(|e| Error::Message(format!("Cannot pass {} to a command: {}", path, e)))
+    };
+    let command_line = command
+        .replace("{input}", &quote(input)?)
+        .replace("{output}", &quote(output)?);
+    log::debug!("Converting with: {}", command_line);
+
+    let status = shell_command(&command_line).status().await?;
+    if !status.success() {
+        return Err(Error::Message(format!(
+            "Converter `{}` failed: {}",
+            command, status
+        )));
+    }
+    if !output.is_file() {
+        return Err(Error::Message(format!(
+            "Converter `{}` did not write {}",
+            command,
+            output.display()
+        )));
+    }
+    Ok(())
+}
+
+/// Typesets `text` in 10 pt Courier on A4 pages, wrapping long lines.
+/// Form feeds start a new page.
+pub fn text_to_pdf(text: &str) -> Result<Vec<u8>, Error> {
+    let mut pages: Vec<Vec<String>> = vec![Vec::new()];
+    for line in text.lines() {
+        for (index, part) in line.split('\x0c').enumerate() {
+            if index > 0 {
+                pages.push(Vec::new());
+            }
+            for wrapped in wrap_line(&part.replace('\t', &" ".repeat(TAB_WIDTH))) {
+                if pages
+                    .last()
+                    .is_some_and(|page| page.len() == LINES_PER_PAGE)
+                {
+                    pages.push(Vec::new());
+                }
+                pages.last_mut().unwrap().push(wrapped);
+            }
+        }
+    }
+
+    let mut doc = Document::with_version("1.5");
+    let font_id = doc.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Courier",
+        "Encoding" => "WinAnsiEncoding",
+    });
+    let resources = dictionary! { "Font" => dictionary! { "F1" => font_id } };
+
+    let contents = pages
+        .iter()
+        .map(|lines| {
+            let mut operations = vec![
+                Operation::new("BT", vec![]),
+                Operation::new("Tf", vec!["F1".into(), FONT_SIZE.into()]),
+                Operation::new("TL", vec![LINE_HEIGHT.into()]),
+                Operation::new(
+                    "Td",
+                    vec![MARGIN.into(), (PAGE_HEIGHT - MARGIN - FONT_SIZE).into()],
+                ),
+            ];
+            for line in lines {
+                operations.push(Operation::new(
+                    "Tj",
+                    vec![Object::string_literal(win_ansi(line))],
+                ));
+                operations.push(Operation::new("T*", vec![]));
+            }
+            operations.push(Operation::new("ET", vec![]));
+            Content { operations }
+        })
+        .collect();
+    write_pdf(doc, resources, contents, [PAGE_WIDTH, PAGE_HEIGHT])
+}
+
+/// Splits `line` into pieces that fit the page, at spaces where possible.
+fn wrap_line(line: &str) -> Vec<String> {
+    let mut wrapped = Vec::new();
+    let mut rest: Vec<char> = line.trim_end().chars().collect();
+    while rest.len() > CHARS_PER_LINE {
+        let split = rest[..=CHARS_PER_LINE]
+            .iter()
+            .rposition(|c| *c == ' ')
+            .filter(|&index| index > 0)
+            .unwrap_or(CHARS_PER_LINE);
+        wrapped.push(
+            rest[..split]
+                .iter()
+                .collect::<String>()
+                .trim_end()
+                .to_string(),
+        );
+        rest = rest[split..].to_vec();
+        while rest.first() == Some(&' ') {
+            rest.remove(0);
+        }
+    }
+    wrapped.push(rest.into_iter().collect());
+    wrapped
+}
+
+/// Encodes `text` for a font with WinAnsiEncoding, which matches Latin-1
+/// for the printable characters. Anything else becomes `?`.
+fn win_ansi(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match u32::from(c) {
+            code @ (0x20..=0x7e | 0xa0..=0xff) => code as u8,
+            _ => b'?',
+        })
+        .collect()
+}
+
+/// Places a PNG or JPEG on an A4 page, rotated to landscape for wide
+/// images and scaled to fit. JPEGs are embedded without re-encoding.
+pub fn image_to_pdf(data: &[u8]) -> Result<Vec<u8>, Error> {
+    let image_error = |e: image::ImageError| Error::Message(format!("Invalid image: {}", e));
+    let format = image::guess_format(data).map_err(image_error)?;
+
+    let passthrough = if format == ImageFormat::Jpeg {
+        let decoder = JpegDecoder::new(Cursor::new(data)).map_err(image_error)?;
+        let color_space = match decoder.color_type() {
+            ColorType::L8 => Some("DeviceGray"),
+            ColorType::Rgb8 => Some("DeviceRGB"),
+            _ => None,
+        };
+        color_space.map(|color_space| (decoder.dimensions(), color_space))
+    } else {
+        None
+    };
+
+    let ((width, height), image) = match passthrough {
+        Some((dimensions, color_space)) => {
+            let stream = Stream::new(
+                image_dictionary(dimensions, color_space, Some("DCTDecode")),
+                data.to_vec(),
+            )
+            .with_compression(false);
+            (dimensions, stream)
+        }
+        None => {
+            let decoded = image::load_from_memory_with_format(data, format).map_err(image_error)?;
+            let pixels = flatten_alpha(decoded);
+            let dimensions = pixels.dimensions();
+            let stream = Stream::new(
+                image_dictionary(dimensions, "DeviceRGB", None),
+                pixels.into_raw(),
+            );
+            (dimensions, stream)
+        }
+    };
+
+    let page = if width > height {
+        [PAGE_HEIGHT, PAGE_WIDTH]
+    } else {
+        [PAGE_WIDTH, PAGE_HEIGHT]
+    };
+    let scale = f32::min(page[0] / width as f32, page[1] / height as f32);
+    let (shown_width, shown_height) = (width as f32 * scale, height as f32 * scale);
+
+    let mut doc = Document::with_version("1.5");
+    let image_id = doc.add_object(image);
+    let resources = dictionary! { "XObject" => dictionary! { "Im1" => image_id } };
+    let content = Content {
+        operations: vec![
+            Operation::new("q", vec![]),
+            Operation::new(
+                "cm",
+                vec![
+                    shown_width.into(),
+                    0.into(),
+                    0.into(),
+                    shown_height.into(),
+                    ((page[0] - shown_width) / 2.0).into(),
+                    ((page[1] - shown_height) / 2.0).into(),
+                ],
+            ),
+            Operation::new("Do", vec!["Im1".into()]),
+            Operation::new("Q", vec![]),
+        ],
+    };
+    write_pdf(doc, resources, vec![content], page)
+}
+
+fn image_dictionary(
+    (width, height): (u32, u32),
+    color_space: &str,
+    filter: Option<&str>,
+) -> lopdf::Dictionary {
+    let mut dict = dictionary! {
+        "Type" => "XObject",
+        "Subtype" => "Image",
+        "Width" => i64::from(width),
+        "Height" => i64::from(height),
+        "ColorSpace" => color_space,
+        "BitsPerComponent" => 8,
+    };
+    if let Some(filter) = filter {
+        dict.set("Filter", filter);
+    }
+    dict
+}
+
+/// Drops transparency by putting the image on white paper.
+fn flatten_alpha(image: DynamicImage) -> image::RgbImage {
+    if !image.color().has_alpha() {
+        return image.to_rgb8();
+    }
+    let rgba = image.to_rgba8();
+    let mut rgb = image::RgbImage::new(rgba.width(), rgba.height());
+    for (target, source) in rgb.pixels_mut().zip(rgba.pixels()) {
+        let [r, g, b, a] = source.0;
+        let blend = |channel: u8| {
+            ((u16::from(channel) * u16::from(a) + 255 * (255 - u16::from(a))) / 255) as u8
+        };
+        *target = image::Rgb([blend(r), blend(g), blend(b)]);
+    }
+    rgb
+}
+
+/// Adds one page per content stream, all sharing `resources` and `size`,
+/// and serializes the document.
+fn write_pdf(
+    mut doc: Document,
+    resources: lopdf::Dictionary,
+    contents: Vec<Content>,
+    size: [f32; 2],
+) -> Result<Vec<u8>, Error> {
+    let pdf_error = |e: lopdf::Error| Error::Message(format!("Failed to write PDF: {}", e));
+
+    let pages_id: ObjectId = doc.new_object_id();
+    let resources_id = doc.add_object(resources);
+    let mut kids = Vec::new();
+    for content in contents {
+        let content_id = doc.add_object(Stream::new(
+            dictionary! {},
+            content.encode().map_err(pdf_error)?,
+        ));
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Contents" => content_id,
+        });
+        kids.push(Object::from(page_id));
+    }
+    let count = kids.len() as i64;
+    doc.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => kids,
+            "Count" => count,
+            "Resources" => resources_id,
+            "MediaBox" => vec![0.into(), 0.into(), size[0].into(), size[1].into()],
+        }),
+    );
+    let catalog_id = doc.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+    });
+    doc.trailer.set("Root", catalog_id);
+    doc.compress();
+
+    let mut data = Vec::new();
+    doc.save_to(&mut data)?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page_count(pdf: &[u8]) -> usize {
+        Document::load_mem(pdf).unwrap().get_pages().len()
+    }
+
+    /// Returns the dictionary and raw content of the only image in `pdf`.
+    fn image_stream(pdf: &[u8]) -> (lopdf::Dictionary, Vec<u8>) {
+        let doc = Document::load_mem(pdf).unwrap();
+        let mut images = doc.objects.values().filter_map(|object| match object {
+            Object::Stream(stream)
+                if stream.dict.get(b"Subtype").ok() == Some(&Object::from("Image")) =>
+            {
+                Some((stream.dict.clone(), stream.content.clone()))
+            }
+            _ => None,
+        });
+        let image = images.next().expect("no image in PDF");
+        assert!(images.next().is_none());
+        image
+    }
+
+    fn encode(image: DynamicImage, format: ImageFormat) -> Vec<u8> {
+        let mut data = Vec::new();
+        image.write_to(&mut Cursor::new(&mut data), format).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_wrap_line() {
+        let exact = "x".repeat(CHARS_PER_LINE);
+        assert_eq!(wrap_line(&exact), vec![exact.clone()]);
+
+        let long = "y".repeat(CHARS_PER_LINE + 1);
+        assert_eq!(
+            wrap_line(&long),
+            vec!["y".repeat(CHARS_PER_LINE), "y".to_string()]
+        );
+
+        // Breaks at the last space that fits, dropping it
+        let words = format!("{} tail", "z".repeat(CHARS_PER_LINE - 2));
+        assert_eq!(
+            wrap_line(&words),
+            vec!["z".repeat(CHARS_PER_LINE - 2), "tail".to_string()]
+        );
+        assert_eq!(wrap_line(""), vec![String::new()]);
+    }
+
+    #[test]
+    fn test_text_to_pdf_pages() {
+        assert_eq!(page_count(&text_to_pdf("hello").unwrap()), 1);
+        assert_eq!(page_count(&text_to_pdf("").unwrap()), 1);
+
+        let full_page = "line\n".repeat(LINES_PER_PAGE);
+        assert_eq!(page_count(&text_to_pdf(&full_page).unwrap()), 1);
+        let overflow = "line\n".repeat(LINES_PER_PAGE + 1);
+        assert_eq!(page_count(&text_to_pdf(&overflow).unwrap()), 2);
+
+        // Form feeds start a new page, also within a line
+        assert_eq!(
+            page_count(&text_to_pdf("one\x0ctwo\n\x0cthree").unwrap()),
+            3
+        );
+    }
+
+    #[test]
+    fn test_image_to_pdf_jpeg_passthrough() {
+        let image = DynamicImage::ImageRgb8(image::RgbImage::from_pixel(
+            40,
+            20,
+            image::Rgb([200, 10, 10]),
+        ));
+        let jpeg = encode(image, ImageFormat::Jpeg);
+
+        let pdf = image_to_pdf(&jpeg).unwrap();
+        assert_eq!(page_count(&pdf), 1);
+        let (dict, content) = image_stream(&pdf);
+        assert_eq!(dict.get(b"Filter").unwrap(), &Object::from("DCTDecode"));
+        assert_eq!(dict.get(b"Width").unwrap(), &Object::from(40));
+        assert_eq!(content, jpeg);
+    }
+
+    #[test]
+    fn test_image_to_pdf_png() {
+        let image = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+            10,
+            30,
+            image::Rgba([0, 0, 0, 0]),
+        ));
+        let pdf = image_to_pdf(&encode(image, ImageFormat::Png)).unwrap();
+        assert_eq!(page_count(&pdf), 1);
+        let (dict, _) = image_stream(&pdf);
+        assert_eq!(dict.get(b"ColorSpace").unwrap(), &Object::from("DeviceRGB"));
+        assert_eq!(dict.get(b"Height").unwrap(), &Object::from(30));
+    }
+
+    #[test]
+    fn test_flatten_alpha() {
+        let mut rgba = image::RgbaImage::new(3, 1);
+        rgba.put_pixel(0, 0, image::Rgba([0, 0, 0, 0]));
+        rgba.put_pixel(1, 0, image::Rgba([10, 20, 30, 255]));
+        rgba.put_pixel(2, 0, image::Rgba([0, 0, 0, 128]));
+
+        let rgb = flatten_alpha(DynamicImage::ImageRgba8(rgba));
+        assert_eq!(rgb.get_pixel(0, 0).0, [255, 255, 255]);
+        assert_eq!(rgb.get_pixel(1, 0).0, [10, 20, 30]);
+        assert_eq!(rgb.get_pixel(2, 0).0, [127, 127, 127]);
+    }
+}
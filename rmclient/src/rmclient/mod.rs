@@ -1,5 +1,11 @@
 pub mod actions;
 pub mod commands;
+pub mod completer;
+pub mod completions;
+pub mod convert;
 pub mod error;
+pub mod output;
+pub mod progress;
 pub mod shell;
 pub mod token;
+pub mod watch;
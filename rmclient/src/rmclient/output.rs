@@ -0,0 +1,218 @@
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use rmapi::objects::{Node, NodeKind};
+use rmapi::usage::SubfileKind;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::rmclient::error::Error;
+
+/// How commands print their results.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// Human-readable text
+    #[default]
+    Table,
+    /// A single JSON document per command
+    Json,
+    /// One JSON object per line
+    Ndjson,
+}
+
+impl OutputFormat {
+    pub fn is_table(self) -> bool {
+        self == OutputFormat::Table
+    }
+
+    /// Prints a list of records as table rows, a JSON array or JSON lines.
+    pub fn list<T: Serialize>(
+        self,
+        records: &[T],
+        row: impl Fn(&T) -> String,
+    ) -> Result<(), Error> {
+        if self.is_table() {
+            for record in records {
+                println!("{}", row(record));
+            }
+            return Ok(());
+        }
+        self.records(records)
+    }
+
+    /// Prints records as a JSON array or JSON lines, leaving table output to the caller.
+    pub fn records<T: Serialize>(self, records: &[T]) -> Result<(), Error> {
+        match self {
+            OutputFormat::Table => {}
+            OutputFormat::Json => println!("{}", to_json(&records, true)?),
+            OutputFormat::Ndjson => {
+                for record in records {
+                    println!("{}", to_json(record, false)?);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints a single record as text, a JSON object or a JSON line.
+    pub fn item<T: Serialize>(
+        self,
+        record: &T,
+        text: impl FnOnce(&T) -> String,
+    ) -> Result<(), Error> {
+        match self {
+            OutputFormat::Table => println!("{}", text(record)),
+            OutputFormat::Json => println!("{}", to_json(record, true)?),
+            OutputFormat::Ndjson => println!("{}", to_json(record, false)?),
+        }
+        Ok(())
+    }
+}
+
+fn to_json<T: Serialize + ?Sized>(record: &T, pretty: bool) -> Result<String, Error> {
+    let json = if pretty {
+        serde_json::to_string_pretty(record)
+    } else {
+        serde_json::to_string(record)
+    };
+    json.map_err(|e| Error::Message(format!("Failed to serialize output: {}", e)))
+}
+
+/// A file or folder of the remote tree.
+#[derive(Debug, Clone, Serialize)]
+pub struct EntryRecord {
+    pub path: PathBuf,
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: NodeKind,
+    /// Size in bytes; for folders, the total of everything below them
+    pub size: u64,
+    /// Number of documents below a folder
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub documents: Option<usize>,
+    pub modified: DateTime<Utc>,
+    pub created: DateTime<Utc>,
+    /// Number of pages of a document
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pages: Option<u32>,
+    pub pinned: bool,
+    pub tags: Vec<String>,
+}
+
+impl EntryRecord {
+    pub fn new(path: &Path, node: &Node) -> Self {
+        EntryRecord {
+            path: path.to_path_buf(),
+            id: node.id(),
+            name: node.name().to_string(),
+            kind: node.kind(),
+            size: node.total_size(),
+            documents: node.is_directory().then(|| node.document_count()),
+            modified: node.document.last_modified,
+            created: node.document.created,
+            pages: (!node.is_directory()).then_some(node.document.page_count),
+            pinned: node.document.bookmarked,
+            tags: node.document.tags.clone(),
+        }
+    }
+}
+
+/// A change made by a command, e.g. a removed or uploaded document.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangeRecord {
+    pub action: &'static str,
+    pub path: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub destination: Option<PathBuf>,
+}
+
+impl ChangeRecord {
+    pub fn new(action: &'static str, path: &Path) -> Self {
+        ChangeRecord {
+            action,
+            path: path.to_path_buf(),
+            destination: None,
+        }
+    }
+
+    pub fn with_destination(mut self, destination: &Path) -> Self {
+        self.destination = Some(destination.to_path_buf());
+        self
+    }
+}
+
+/// A line of text that matched a `grep` pattern.
+#[derive(Debug, Clone, Serialize)]
+pub struct MatchRecord {
+    pub path: PathBuf,
+    pub id: String,
+    pub page: u32,
+    pub line: String,
+}
+
+/// Storage used by a document or folder, per kind of subfile.
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageRecord {
+    pub path: PathBuf,
+    pub size: u64,
+    pub breakdown: BTreeMap<SubfileKind, u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageReport {
+    pub entries: Vec<UsageRecord>,
+    /// Usage of the requested path as a whole
+    pub total: UsageRecord,
+    /// Size of the whole account, trash included
+    pub account_size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quota: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FailureRecord {
+    pub path: PathBuf,
+    pub error: String,
+}
+
+/// A document whose PDF was swapped by `put --replace`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplaceRecord {
+    pub path: PathBuf,
+    pub destination: PathBuf,
+    pub pages_before: u32,
+    pub pages_after: u32,
+    pub removed_pages: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedRecord {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UploadSummary {
+    pub uploaded: Vec<ChangeRecord>,
+    /// Local directories recreated as folders
+    pub folders: Vec<ChangeRecord>,
+    pub skipped: Vec<SkippedRecord>,
+    pub failed: Vec<FailureRecord>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupSummary {
+    pub downloaded: Vec<PathBuf>,
+    pub moved: Vec<ChangeRecord>,
+    pub unchanged: usize,
+    pub removed: Vec<PathBuf>,
+    pub failed: Vec<FailureRecord>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RestoreSummary {
+    pub restored: Vec<PathBuf>,
+    pub skipped: usize,
+    pub failed: Vec<FailureRecord>,
+}
@@ -0,0 +1,130 @@
+use indicatif::{ProgressBar, ProgressStyle};
+use rmapi::progress::Progress;
+use std::io::IsTerminal;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::rmclient::actions::format_size;
+
+/// Time between two progress lines when stderr is not a terminal
+const LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Progress bars on a terminal, periodic lines on stderr otherwise.
+pub fn reporter() -> Arc<dyn Progress> {
+    if std::io::stderr().is_terminal() {
+        Arc::new(BarProgress::default())
+    } else {
+        Arc::new(LogProgress::default())
+    }
+}
+
+/// Draws a progress bar of the transferred bytes with the document count as
+/// its message.
+#[derive(Default)]
+struct BarProgress {
+    // Only set while a transfer runs, so stray updates are ignored
+    state: Mutex<Option<(ProgressBar, usize, usize)>>,
+}
+
+impl Progress for BarProgress {
+    fn start(&self, documents: usize, bytes: u64) {
+        let bar = ProgressBar::new(bytes);
+        let style = ProgressStyle::with_template(
+            "{msg:>16} [{bar:30}] {bytes}/{total_bytes} {bytes_per_sec} {eta}",
+        )
+        .unwrap_or_else(|_| ProgressStyle::default_bar())
+        .progress_chars("=> ");
+        bar.set_style(style);
+        bar.set_message(format!("0/{} documents", documents));
+        *self.state.lock().unwrap() = Some((bar, 0, documents));
+    }
+
+    fn advance(&self, bytes: u64) {
+        if let Some((bar, _, _)) = self.state.lock().unwrap().as_ref() {
+            bar.inc(bytes);
+        }
+    }
+
+    fn document_done(&self, _name: &str) {
+        if let Some((bar, done, total)) = self.state.lock().unwrap().as_mut() {
+            *done += 1;
+            bar.set_message(format!("{}/{} documents", done, total));
+        }
+    }
+
+    fn finish(&self) {
+        if let Some((bar, _, _)) = self.state.lock().unwrap().take() {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+#[derive(Default)]
+struct LogProgress {
+    state: Mutex<Option<LogState>>,
+}
+
+struct LogState {
+    documents: usize,
+    documents_done: usize,
+    bytes: u64,
+    bytes_done: u64,
+    last_line: Instant,
+    printed: bool,
+}
+
+impl LogState {
+    fn print(&mut self) {
+        eprintln!(
+            "Progress: {}/{} documents, {} of {}",
+            self.documents_done,
+            self.documents,
+            format_size(self.bytes_done),
+            format_size(self.bytes)
+        );
+        self.last_line = Instant::now();
+        self.printed = true;
+    }
+
+    fn print_if_due(&mut self) {
+        if self.last_line.elapsed() >= LOG_INTERVAL {
+            self.print();
+        }
+    }
+}
+
+impl Progress for LogProgress {
+    fn start(&self, documents: usize, bytes: u64) {
+        *self.state.lock().unwrap() = Some(LogState {
+            documents,
+            documents_done: 0,
+            bytes,
+            bytes_done: 0,
+            last_line: Instant::now(),
+            printed: false,
+        });
+    }
+
+    fn advance(&self, bytes: u64) {
+        if let Some(state) = self.state.lock().unwrap().as_mut() {
+            state.bytes_done += bytes;
+            state.print_if_due();
+        }
+    }
+
+    fn document_done(&self, _name: &str) {
+        if let Some(state) = self.state.lock().unwrap().as_mut() {
+            state.documents_done += 1;
+            state.print_if_due();
+        }
+    }
+
+    fn finish(&self) {
+        // Short transfers finish without a single line
+        if let Some(mut state) = self.state.lock().unwrap().take() {
+            if state.printed {
+                state.print();
+            }
+        }
+    }
+}
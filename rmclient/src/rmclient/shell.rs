@@ -1,11 +1,26 @@
 use crate::rmclient::actions;
+use crate::rmclient::commands::{DuArgs, DuSort, FindArgs, GetArgs, LsArgs, TreeArgs};
+use crate::rmclient::completer::ShellHelper;
+use crate::rmclient::convert::{default_converters_path, ConverterRegistry};
 use crate::rmclient::error::Error;
-use clap::Parser;
+use crate::rmclient::output::OutputFormat;
+use clap::{CommandFactory, Parser};
 use rmapi::RmClient;
 use rustyline::error::ReadlineError;
-use rustyline::DefaultEditor;
+use rustyline::history::DefaultHistory;
+use rustyline::Editor;
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 
+const HISTORY_FILE_NAME: &str = "shell_history";
+
+/// Commands in this file run at shell start, e.g. aliases and a default `cd`
+pub fn default_shellrc_path() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join("rmapi/shellrc")
+}
+
 #[derive(Parser, Debug)]
 #[command(name = "", no_binary_name = true)]
 enum ShellCommand {
@@ -13,6 +28,30 @@ enum ShellCommand {
     Ls {
         /// Optional path to list
         path: Option<PathBuf>,
+        #[command(flatten)]
+        options: LsArgs,
+    },
+    /// Show the folder hierarchy with document counts and sizes
+    Tree {
+        /// Directory to show (defaults to current directory)
+        path: Option<PathBuf>,
+        #[command(flatten)]
+        options: TreeArgs,
+    },
+    /// Show storage used per document and folder, by kind of file
+    Du {
+        /// File or directory to report on (defaults to current directory)
+        path: Option<PathBuf>,
+        #[command(flatten)]
+        options: DuArgs,
+    },
+    /// Show the metadata and stored files of a document or folder
+    Stat {
+        /// Path of the document or folder
+        path: PathBuf,
+        /// Print the raw information as JSON
+        #[arg(long)]
+        json: bool,
     },
     /// Change the current directory
     Cd {
@@ -21,65 +60,178 @@ enum ShellCommand {
     },
     /// Print the current working directory
     Pwd,
+    /// Change the local directory used by put and get
+    Lcd {
+        /// Local path to navigate to (defaults to the home directory)
+        path: Option<PathBuf>,
+    },
+    /// List files in the current or specified local directory
+    Lls {
+        /// Optional local path to list
+        path: Option<PathBuf>,
+    },
+    /// Print the local working directory
+    Lpwd,
     /// Exit the shell
     Exit,
     /// Alias for Exit
     /// Alias for Exit
     Quit,
-    /// Remove a file
+    /// Remove files (supports wildcards)
     Rm {
-        /// Name of the file to remove
-        path: PathBuf,
+        /// Names or glob patterns of the files to remove
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
     },
-    /// Upload a file
+    /// Upload files or directories
     Put {
-        /// Local path to the file to upload
-        path: PathBuf,
-        /// Optional target directory (defaults to current directory)
-        destination: Option<PathBuf>,
-    },
-    /// Download a file or directory
-    Get {
-        /// Path of the file/directory to download
-        path: PathBuf,
-        /// Recursive download
+        /// Local files to upload, relative to the local directory, followed by
+        /// the target directory when more than one path is given and the last
+        /// one does not exist locally (defaults to current directory)
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// Target directory, so every path is uploaded
+        #[arg(short, long, value_name = "REMOTE", conflicts_with = "replace")]
+        dest: Option<PathBuf>,
+        /// Upload directories with their contents, recreating them as folders
         #[arg(short, long)]
         recursive: bool,
+        /// Replace the PDF of this existing document, keeping its annotations
+        #[arg(long, value_name = "REMOTE", conflicts_with = "recursive")]
+        replace: Option<PathBuf>,
+    },
+    /// Download files or directories into the local directory (supports wildcards)
+    Get {
+        /// Paths or glob patterns of the files/directories to download
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        #[command(flatten)]
+        options: GetArgs,
     },
-    /// Move a file or directory
+    /// Move or rename files or directories (supports wildcards)
     Mv {
-        /// Name of the file/directory to move
-        path: PathBuf,
-        /// Destination path
+        /// Names or glob patterns of the files/directories to move
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// Destination path (has to be a directory when moving several entries)
         destination: PathBuf,
     },
+    /// Search the file tree by name, type, date, pinned state and tags
+    Find {
+        /// Directory to search in (defaults to current directory)
+        root: Option<PathBuf>,
+        #[command(flatten)]
+        filters: FindArgs,
+    },
+    /// Search the text of documents
+    Grep {
+        /// Regular expression to search for
+        pattern: String,
+        /// File or directory to search in (defaults to current directory)
+        path: Option<PathBuf>,
+        /// Ignore case when matching
+        #[arg(short, long)]
+        ignore_case: bool,
+    },
+    /// Define an alias (NAME=COMMAND) or list all aliases
+    Alias {
+        /// Definition such as ll='ls -l'
+        definition: Option<String>,
+    },
+    /// Remove an alias
+    Unalias {
+        /// Name of the alias
+        name: String,
+    },
 }
 
 pub struct Shell {
     client: RmClient,
     current_path: PathBuf,
+    /// Directory `put` sources and `get` destinations are relative to
+    local_path: PathBuf,
     token_file_path: PathBuf,
+    output: OutputFormat,
+    aliases: BTreeMap<String, String>,
+    converters: ConverterRegistry,
 }
 
 impl Shell {
-    pub fn new(client: RmClient, token_file_path: PathBuf) -> Self {
+    pub fn new(mut client: RmClient, token_file_path: PathBuf, output: OutputFormat) -> Self {
+        client.set_progress(crate::rmclient::progress::reporter());
+        let converters = ConverterRegistry::load(&default_converters_path()).unwrap_or_else(|e| {
+            log::warn!("Using the built-in converters only: {}", e);
+            ConverterRegistry::default()
+        });
         Shell {
             client,
             current_path: PathBuf::from("/"),
+            local_path: std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
             token_file_path,
+            output,
+            aliases: BTreeMap::new(),
+            converters,
         }
     }
 
+    /// Runs the commands in `script` one line at a time, stopping at the
+    /// first one that fails. `source` names the script in error messages.
+    pub async fn run_script(&mut self, script: &str, source: &str) -> Result<(), Error> {
+        let mut completed = 0;
+        for (number, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match self.execute_line(line).await {
+                Ok(true) => break,
+                Ok(false) => completed += 1,
+                Err(e) => {
+                    return Err(Error::Message(format!(
+                        "{}:{}: {}: {}\nStopped after {} successful command(s)",
+                        source,
+                        number + 1,
+                        line,
+                        e.to_string().trim_end(),
+                        completed
+                    )))
+                }
+            }
+        }
+        log::info!("{}: {} command(s) completed", source, completed);
+        Ok(())
+    }
+
     pub async fn run(&mut self) -> Result<(), Error> {
         println!("Welcome to rmapi-rs shell!");
         println!("Loading file tree...");
         crate::rmclient::token::refetch_if_unauthorized(&mut self.client, &self.token_file_path)
             .await?;
 
-        let mut rl: DefaultEditor =
-            DefaultEditor::new().map_err(|e| Error::Message(e.to_string()))?;
+        let mut rl: Editor<ShellHelper, DefaultHistory> =
+            Editor::new().map_err(|e| Error::Message(e.to_string()))?;
+        let commands = ShellCommand::command()
+            .get_subcommands()
+            .map(|command| command.get_name().to_string())
+            .collect();
+        rl.set_helper(Some(ShellHelper::new(commands)));
+
+        // History lives next to the auth token
+        let history_path = self.token_file_path.with_file_name(HISTORY_FILE_NAME);
+        if let Err(e) = rl.load_history(&history_path) {
+            log::debug!("No shell history loaded from {:?}: {}", history_path, e);
+        }
+
+        self.run_shellrc(&default_shellrc_path()).await;
 
         loop {
+            if let Some(helper) = rl.helper_mut() {
+                helper.update(
+                    &self.client.filesystem,
+                    &self.current_path,
+                    &self.local_path,
+                );
+            }
             let prompt = format!("[{}]> ", self.current_path.display());
             match rl.readline(&prompt) {
                 Ok(line) => {
@@ -91,47 +243,133 @@ impl Shell {
                 Err(err) => return Err(Error::Message(format!("Readline error: {:?}", err))),
             }
         }
+
+        if let Some(parent) = history_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        if let Err(e) = rl.save_history(&history_path) {
+            log::warn!("Failed to save shell history to {:?}: {}", history_path, e);
+        }
         Ok(())
     }
 
-    async fn handle_input(&mut self, line: String, rl: &mut DefaultEditor) -> Result<bool, Error> {
+    /// Runs every line of the startup file. Failing lines are reported but do
+    /// not stop the shell from starting.
+    async fn run_shellrc(&mut self, path: &Path) {
+        let Ok(content) = std::fs::read_to_string(path) else {
+            return;
+        };
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Err(e) = self.execute_line(line).await {
+                println!("{}:{}: {}", path.display(), number + 1, e);
+            }
+        }
+    }
+
+    async fn handle_input(
+        &mut self,
+        line: String,
+        rl: &mut Editor<ShellHelper, DefaultHistory>,
+    ) -> Result<bool, Error> {
         let line = line.trim();
         if line.is_empty() {
             return Ok(false);
         }
         let _ = rl.add_history_entry(line);
 
-        let parts = shlex::split(line).unwrap_or_default();
+        // A failing command is reported and the shell keeps going
+        match self.execute_line(line).await {
+            Ok(exit) => Ok(exit),
+            Err(Error::Clap(e)) => {
+                println!("{}", e);
+                Ok(false)
+            }
+            Err(e) => {
+                println!("Error: {}", e);
+                Ok(false)
+            }
+        }
+    }
+
+    async fn execute_line(&mut self, line: &str) -> Result<bool, Error> {
+        let mut parts =
+            shlex::split(line).ok_or_else(|| Error::Message("Unbalanced quotes".to_string()))?;
         if parts.is_empty() {
             return Ok(false);
         }
 
+        // Aliases are expanded once, so an alias may refer to the command it shadows
+        if let Some(expansion) = self.aliases.get(&parts[0]) {
+            let mut expanded = shlex::split(expansion).ok_or_else(|| {
+                Error::Message(format!("Unbalanced quotes in alias {}", parts[0]))
+            })?;
+            expanded.extend(parts.drain(1..));
+            parts = expanded;
+        }
+
         match ShellCommand::try_parse_from(&parts) {
-            Ok(cmd) => self.handle_command(cmd).await,
-            Err(e) => {
+            Ok(cmd) => {
+                let result = self.handle_command(cmd).await;
+                self.client.prune_blob_cache();
+                result
+            }
+            Err(e) if !e.use_stderr() => {
+                // --help and --version end up here
                 println!("{}", e);
                 Ok(false)
             }
+            Err(e) => Err(Error::Clap(e)),
         }
     }
 
     async fn handle_command(&mut self, cmd: ShellCommand) -> Result<bool, Error> {
         match cmd {
-            ShellCommand::Ls { path } => self.exec_ls(path.as_deref()).await?,
+            ShellCommand::Ls { path, options } => self.exec_ls(path.as_deref(), &options).await?,
+            ShellCommand::Tree { path, options } => self.exec_tree(path.as_deref(), options)?,
+            ShellCommand::Du { path, options } => self.exec_du(path.as_deref(), options).await?,
+            ShellCommand::Stat { path, json } => self.exec_stat(&path, json).await?,
             ShellCommand::Cd { path } => self.exec_cd(path.as_deref()).await?,
             ShellCommand::Pwd => println!("{}", self.current_path.display()),
+            ShellCommand::Lcd { path } => self.exec_lcd(path.as_deref())?,
+            ShellCommand::Lls { path } => self.exec_lls(path.as_deref())?,
+            ShellCommand::Lpwd => println!("{}", self.local_path.display()),
             ShellCommand::Exit | ShellCommand::Quit => return Ok(true),
-            ShellCommand::Rm { path } => self.exec_rm(&path).await?,
-            ShellCommand::Put { path, destination } => {
-                self.exec_put(&path, destination.as_deref()).await?
+            ShellCommand::Alias { definition } => self.exec_alias(definition.as_deref())?,
+            ShellCommand::Unalias { name } => {
+                if self.aliases.remove(&name).is_none() {
+                    return Err(Error::Message(format!("No such alias: {}", name)));
+                }
+            }
+            ShellCommand::Rm { paths } => self.exec_rm(&paths).await?,
+            ShellCommand::Put {
+                paths,
+                dest,
+                recursive,
+                replace,
+            } => match replace {
+                Some(remote) => self.exec_replace(&paths, &remote).await?,
+                None => self.exec_put(&paths, dest.as_ref(), recursive).await?,
+            },
+            ShellCommand::Get { paths, options } => self.exec_get(&paths, &options).await?,
+            ShellCommand::Mv { paths, destination } => self.exec_mv(&paths, &destination).await?,
+            ShellCommand::Find { root, filters } => self.exec_find(root.as_deref(), filters)?,
+            ShellCommand::Grep {
+                pattern,
+                path,
+                ignore_case,
+            } => {
+                self.exec_grep(&pattern, path.as_deref(), ignore_case)
+                    .await?
             }
-            ShellCommand::Get { path, recursive } => self.exec_get(&path, recursive).await?,
-            ShellCommand::Mv { path, destination } => self.exec_mv(&path, &destination).await?,
         }
         Ok(false)
     }
 
-    async fn exec_ls(&mut self, path: Option<&Path>) -> Result<(), Error> {
+    async fn exec_ls(&mut self, path: Option<&Path>, options: &LsArgs) -> Result<(), Error> {
         let target_buf;
         let target = if let Some(p) = path {
             target_buf = rmapi::filesystem::normalize_path(p, &self.current_path);
@@ -140,22 +378,77 @@ impl Shell {
             &self.current_path
         };
 
-        actions::ls(&self.client, target).await
+        actions::ls(&self.client, self.output, target, options).await
     }
 
-    async fn exec_rm(&mut self, path: &Path) -> Result<(), Error> {
-        let target = rmapi::filesystem::normalize_path(path, &self.current_path);
-
-        if target == Path::new("/") {
-            println!("Error: Cannot remove the root directory.");
+    fn exec_alias(&mut self, definition: Option<&str>) -> Result<(), Error> {
+        let Some(definition) = definition else {
+            for (name, expansion) in &self.aliases {
+                println!(
+                    "alias {}={}",
+                    name,
+                    shlex::try_quote(expansion).unwrap_or_default()
+                );
+            }
             return Ok(());
-        }
+        };
+
+        let (name, expansion) = definition
+            .split_once('=')
+            .filter(|(name, expansion)| !name.is_empty() && !expansion.trim().is_empty())
+            .ok_or_else(|| {
+                Error::Message(format!("Invalid alias: {} (use NAME=COMMAND)", definition))
+            })?;
+        self.aliases
+            .insert(name.to_string(), expansion.trim().to_string());
+        Ok(())
+    }
+
+    fn exec_tree(&mut self, path: Option<&Path>, options: TreeArgs) -> Result<(), Error> {
+        let target = match path {
+            Some(p) => rmapi::filesystem::normalize_path(p, &self.current_path),
+            None => self.current_path.clone(),
+        };
+        actions::tree(
+            &self.client,
+            self.output,
+            &target,
+            options.depth,
+            options.size,
+        )
+    }
+
+    async fn exec_du(&mut self, path: Option<&Path>, options: DuArgs) -> Result<(), Error> {
+        let target = match path {
+            Some(p) => rmapi::filesystem::normalize_path(p, &self.current_path),
+            None => self.current_path.clone(),
+        };
+        actions::du(
+            &self.client,
+            self.output,
+            &target,
+            options.sort == DuSort::Size,
+            options.quota,
+        )
+        .await
+    }
 
-        actions::rm(&self.client, &target).await?;
+    async fn exec_stat(&mut self, path: &Path, json: bool) -> Result<(), Error> {
+        let target = rmapi::filesystem::normalize_path(path, &self.current_path);
+        let output = if json {
+            OutputFormat::Json
+        } else {
+            self.output
+        };
+        actions::stat(&self.client, output, &target).await
+    }
+
+    async fn exec_rm(&mut self, paths: &[PathBuf]) -> Result<(), Error> {
+        let targets = self.normalize_paths(paths);
+        actions::rm(&self.client, self.output, &targets).await?;
 
         // Refresh file list
         self.client.list_files().await?;
-        println!("Removed {}", target.display());
         Ok(())
     }
 
@@ -168,40 +461,163 @@ impl Shell {
             }
         };
 
-        match actions::cd(&self.client, &target) {
-            Ok(_) => self.current_path = target,
-            Err(e) => println!("{}", e),
+        actions::cd(&self.client, &target)?;
+        self.current_path = target;
+        Ok(())
+    }
+
+    fn exec_lcd(&mut self, path: Option<&Path>) -> Result<(), Error> {
+        let target = match path {
+            Some(p) => self.local_path.join(p),
+            None => {
+                dirs::home_dir().ok_or_else(|| Error::Message("No home directory".to_string()))?
+            }
+        };
+        // Canonicalizing resolves `..` and checks that the directory exists
+        let target = target
+            .canonicalize()
+            .map_err(|e| Error::Message(format!("{}: {}", target.display(), e)))?;
+        if !target.is_dir() {
+            return Err(Error::Message(format!(
+                "Not a directory: {}",
+                target.display()
+            )));
         }
+        self.local_path = target;
         Ok(())
     }
 
-    async fn exec_put(&mut self, path: &Path, destination: Option<&Path>) -> Result<(), Error> {
-        let destination_path = if let Some(dest) = destination {
-            Some(rmapi::filesystem::normalize_path(dest, &self.current_path))
-        } else {
-            None
+    fn exec_lls(&self, path: Option<&Path>) -> Result<(), Error> {
+        let target = match path {
+            Some(p) => self.local_path.join(p),
+            None => self.local_path.clone(),
         };
+        let metadata = std::fs::metadata(&target)
+            .map_err(|e| Error::Message(format!("{}: {}", target.display(), e)))?;
+        if !metadata.is_dir() {
+            println!("{}", target.display());
+            return Ok(());
+        }
+
+        let mut entries = std::fs::read_dir(&target)?
+            .map(|entry| {
+                let entry = entry?;
+                let mut name = entry.file_name().to_string_lossy().into_owned();
+                if entry.file_type()?.is_dir() {
+                    name.push('/');
+                }
+                Ok(name)
+            })
+            .collect::<Result<Vec<_>, std::io::Error>>()?;
+        entries.sort_by_key(|name| name.to_lowercase());
+        for name in entries {
+            println!("{}", name);
+        }
+        Ok(())
+    }
+
+    async fn exec_put(
+        &mut self,
+        paths: &[PathBuf],
+        dest: Option<&PathBuf>,
+        recursive: bool,
+    ) -> Result<(), Error> {
+        let (sources, destination) = actions::split_destination(paths, dest, &self.local_path);
+        let sources: Vec<PathBuf> = sources
+            .iter()
+            .map(|path| self.local_path.join(path))
+            .collect();
+        let destination_path = destination
+            .map(|dest| rmapi::filesystem::normalize_path(dest, &self.current_path))
+            .unwrap_or_else(|| self.current_path.clone());
+
+        let result = actions::put(
+            &self.client,
+            self.output,
+            &sources,
+            &destination_path,
+            recursive,
+            &self.converters,
+        )
+        .await;
+
+        // Refresh file list, also after a partial upload
+        self.client.list_files().await?;
+        result
+    }
 
-        actions::put(&mut self.client, path, destination_path.as_deref()).await?;
+    async fn exec_replace(&mut self, paths: &[PathBuf], remote: &Path) -> Result<(), Error> {
+        let [path] = paths else {
+            return Err(Error::Message(
+                "--replace takes exactly one local file".to_string(),
+            ));
+        };
+        let target = rmapi::filesystem::normalize_path(remote, &self.current_path);
+        actions::replace(
+            &self.client,
+            self.output,
+            &self.local_path.join(path),
+            &target,
+        )
+        .await?;
 
         // Refresh file list
         self.client.list_files().await?;
         Ok(())
     }
 
-    async fn exec_get(&mut self, path: &Path, recursive: bool) -> Result<(), Error> {
-        let target = rmapi::filesystem::normalize_path(path, &self.current_path);
-        actions::get(&self.client, &target, recursive).await
+    async fn exec_get(&mut self, paths: &[PathBuf], options: &GetArgs) -> Result<(), Error> {
+        let targets = self.normalize_paths(paths);
+        let destination = match &options.dest {
+            Some(dest) => self.local_path.join(dest),
+            None => self.local_path.clone(),
+        };
+        actions::get(
+            &self.client,
+            self.output,
+            &targets,
+            &destination,
+            options.into(),
+        )
+        .await
     }
 
-    async fn exec_mv(&mut self, path: &Path, destination: &Path) -> Result<(), Error> {
-        let src_target = rmapi::filesystem::normalize_path(path, &self.current_path);
+    async fn exec_mv(&mut self, paths: &[PathBuf], destination: &Path) -> Result<(), Error> {
+        let src_targets = self.normalize_paths(paths);
         let dest_target = rmapi::filesystem::normalize_path(destination, &self.current_path);
 
-        actions::mv(&self.client, &src_target, &dest_target).await?;
+        actions::mv(&self.client, self.output, &src_targets, &dest_target).await?;
 
         // Refresh file list
         self.client.list_files().await?;
         Ok(())
     }
+
+    fn normalize_paths(&self, paths: &[PathBuf]) -> Vec<PathBuf> {
+        paths
+            .iter()
+            .map(|path| rmapi::filesystem::normalize_path(path, &self.current_path))
+            .collect()
+    }
+
+    fn exec_find(&mut self, root: Option<&Path>, filters: FindArgs) -> Result<(), Error> {
+        let target = match root {
+            Some(p) => rmapi::filesystem::normalize_path(p, &self.current_path),
+            None => self.current_path.clone(),
+        };
+        actions::find(&self.client, self.output, &target, &filters.into())
+    }
+
+    async fn exec_grep(
+        &mut self,
+        pattern: &str,
+        path: Option<&Path>,
+        ignore_case: bool,
+    ) -> Result<(), Error> {
+        let target = match path {
+            Some(p) => rmapi::filesystem::normalize_path(p, &self.current_path),
+            None => self.current_path.clone(),
+        };
+        actions::grep(&self.client, self.output, pattern, &target, ignore_case).await
+    }
 }
@@ -0,0 +1,355 @@
+use futures::StreamExt;
+use notify::RecursiveMode;
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult, DebouncedEventKind};
+use rmapi::changes::ChangeEvent;
+use rmapi::upload::is_supported;
+use rmapi::RmClient;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::rmclient::actions::shell_command;
+use crate::rmclient::commands::WatchArgs;
+use crate::rmclient::error::Error;
+use crate::rmclient::output::{ChangeRecord, FailureRecord, OutputFormat};
+use crate::rmclient::token::{refetch_if_unauthorized, write_token_file};
+
+/// Remembers what was uploaded, so restarting the watcher does not upload
+/// the files that are still in the directory again
+const STATE_FILE_NAME: &str = ".rmclient-watch.json";
+/// Uploaded files are moved here with `--done`
+pub const DONE_DIR_NAME: &str = "done";
+/// How long a file's size has to stay the same before it is uploaded
+const SIZE_CHECK_DELAY: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct WatchState {
+    /// SHA-256 of every file uploaded from the directory
+    uploaded: BTreeSet<String>,
+}
+
+impl WatchState {
+    fn load(dir: &Path) -> Result<Self, Error> {
+        let path = dir.join(STATE_FILE_NAME);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let data = std::fs::read_to_string(path)?;
+        serde_json::from_str(&data)
+            .map_err(|e| Error::Message(format!("Invalid {}: {}", STATE_FILE_NAME, e)))
+    }
+
+    fn save(&self, dir: &Path) -> Result<(), Error> {
+        let data = serde_json::to_string_pretty(self).map_err(|e| Error::Message(e.to_string()))?;
+        std::fs::write(dir.join(STATE_FILE_NAME), data)?;
+        Ok(())
+    }
+}
+
+/// Uploads the PDFs and EPUBs that appear in `local_dir` into `remote_dir`
+/// until interrupted.
+///
+/// Files already in the directory are handled first. A file is picked up
+/// once no events arrived for it during the debounce time and its size
+/// stopped changing, and files whose content was uploaded before are
+/// skipped. Failed uploads are reported and retried the next time the file
+/// changes.
+pub async fn watch(
+    client: &mut RmClient,
+    output: OutputFormat,
+    auth_token_file: &Path,
+    local_dir: &Path,
+    remote_dir: &Path,
+    options: &WatchArgs,
+) -> Result<(), Error> {
+    if !local_dir.is_dir() {
+        return Err(Error::Message(format!(
+            "Not a local directory: {}",
+            local_dir.display()
+        )));
+    }
+    let local_dir = local_dir.canonicalize()?;
+    let node = client.filesystem.find_node_by_path(remote_dir)?;
+    if !node.is_directory() {
+        return Err(Error::Message(format!(
+            "Destination is not a directory: {}",
+            remote_dir.display()
+        )));
+    }
+
+    let mut watcher = HotFolder {
+        state: WatchState::load(&local_dir)?,
+        local_dir,
+        remote_dir: remote_dir.to_path_buf(),
+        move_done: options.done,
+        output,
+    };
+
+    let debounce = Duration::try_from_secs_f64(options.debounce)
+        .map_err(|e| Error::Message(format!("Invalid debounce time: {}", e)))?;
+
+    // The debouncer calls back on its own thread
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<DebounceEventResult>();
+    let mut debouncer = new_debouncer(debounce, move |res| {
+        let _ = tx.send(res);
+    })
+    .map_err(|e| Error::Message(format!("Failed to watch directory: {}", e)))?;
+    debouncer
+        .watcher()
+        .watch(&watcher.local_dir, RecursiveMode::NonRecursive)
+        .map_err(|e| Error::Message(format!("Failed to watch directory: {}", e)))?;
+    log::info!(
+        "Watching {} for files to upload to {}",
+        watcher.local_dir.display(),
+        remote_dir.display()
+    );
+
+    let existing = std::fs::read_dir(&watcher.local_dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    watcher
+        .upload_batch(client, auth_token_file, existing)
+        .await?;
+
+    loop {
+        tokio::select! {
+            events = rx.recv() => {
+                let Some(events) = events else {
+                    break;
+                };
+                // `AnyContinuous` fires while events keep coming in, i.e. while
+                // the file is most likely still being written
+                let paths = match events {
+                    Ok(events) => events
+                        .into_iter()
+                        .filter(|event| event.kind == DebouncedEventKind::Any)
+                        .map(|event| event.path)
+                        .collect(),
+                    Err(e) => {
+                        log::warn!("Watch error: {}", e);
+                        continue;
+                    }
+                };
+                watcher.upload_batch(client, auth_token_file, paths).await?;
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+    Ok(())
+}
+
+/// Prints every change made in the cloud until interrupted, and runs
+/// `exec` through the shell for each of them.
+///
+/// The hook finds the change in environment variables, so exporting a
+/// notebook whenever it is edited on the tablet is a matter of
+/// `--exec 'rmclient get -o ~/export --overwrite "$RMAPI_PATH"'`. A failing
+/// hook is reported and does not stop the watcher.
+pub async fn watch_remote(
+    client: &mut RmClient,
+    output: OutputFormat,
+    auth_token_file: &Path,
+    interval: Duration,
+    exec: Option<&str>,
+) -> Result<(), Error> {
+    log::info!("Watching the cloud for changes every {:?}", interval);
+    let mut snapshot = None;
+    loop {
+        {
+            let changes = client.watch_changes(interval, &mut snapshot);
+            futures::pin_mut!(changes);
+            loop {
+                let change = tokio::select! {
+                    change = changes.next() => change,
+                    _ = tokio::signal::ctrl_c() => return Ok(()),
+                };
+                match change {
+                    Some(Ok(event)) => {
+                        output.item(&event, |event| match &event.previous_path {
+                            Some(previous) => format!(
+                                "{} {} (was {})",
+                                event.kind,
+                                event.path.display(),
+                                previous.display()
+                            ),
+                            None => format!("{} {}", event.kind, event.path.display()),
+                        })?;
+                        if let Some(command) = exec {
+                            run_hook(command, &event).await;
+                        }
+                    }
+                    Some(Err(e)) if e.is_unauthorized() => break,
+                    Some(Err(e)) => log::warn!("Failed to check for changes: {}", e),
+                    None => return Ok(()),
+                }
+            }
+        }
+        // The restarted stream picks up from the last snapshot, so nothing
+        // that changed in the meantime is missed
+        log::info!("Token expired, refreshing...");
+        client.refresh_user_token().await?;
+        write_token_file(client, auth_token_file).await?;
+    }
+}
+
+async fn run_hook(command: &str, event: &ChangeEvent) {
+    let mut hook = shell_command(command);
+    let previous_path = event
+        .previous_path
+        .as_deref()
+        .unwrap_or(Path::new(""))
+        .as_os_str();
+    hook.env("RMAPI_EVENT", event.kind.to_string())
+        .env("RMAPI_ID", &event.id)
+        .env("RMAPI_PATH", &event.path)
+        .env("RMAPI_PREVIOUS_PATH", previous_path)
+        .env(
+            "RMAPI_TYPE",
+            if event.is_folder {
+                "folder"
+            } else {
+                "document"
+            },
+        );
+
+    match hook.status().await {
+        Ok(status) if status.success() => {}
+        Ok(status) => log::warn!(
+            "Hook for {} {} failed: {}",
+            event.kind,
+            event.path.display(),
+            status
+        ),
+        Err(e) => log::warn!("Failed to run hook: {}", e),
+    }
+}
+
+/// Keeps the files whose size does not change within [`SIZE_CHECK_DELAY`].
+///
+/// Others are still being written; they are picked up by the events of the
+/// following writes.
+async fn stable_files(paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    let size = |path: &Path| std::fs::metadata(path).map(|m| m.len()).ok();
+    let sizes: Vec<Option<u64>> = paths.iter().map(|path| size(path)).collect();
+    tokio::time::sleep(SIZE_CHECK_DELAY).await;
+
+    paths
+        .into_iter()
+        .zip(sizes)
+        .filter(|(path, before)| {
+            let stable = before.is_some() && size(path) == *before;
+            if !stable {
+                log::info!("{} is still being written, waiting", path.display());
+            }
+            stable
+        })
+        .map(|(path, _)| path)
+        .collect()
+}
+
+struct HotFolder {
+    local_dir: PathBuf,
+    remote_dir: PathBuf,
+    state: WatchState,
+    move_done: bool,
+    output: OutputFormat,
+}
+
+impl HotFolder {
+    /// Uploads every candidate among `paths`. Only errors that make further
+    /// uploads pointless, such as a rejected token, are returned.
+    async fn upload_batch(
+        &mut self,
+        client: &mut RmClient,
+        auth_token_file: &Path,
+        paths: Vec<PathBuf>,
+    ) -> Result<(), Error> {
+        let candidates: Vec<PathBuf> = paths
+            .into_iter()
+            .filter(|path| self.is_candidate(path))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        let candidates = stable_files(candidates).await;
+        if candidates.is_empty() {
+            return Ok(());
+        }
+
+        // Also picks up folders created since the last batch
+        refetch_if_unauthorized(client, auth_token_file).await?;
+        let parent_id = client.filesystem.find_node_by_path(&self.remote_dir)?.id();
+
+        for path in candidates {
+            if let Err(e) = self.upload_file(client, &path, &parent_id).await {
+                let failure = FailureRecord {
+                    path: path.clone(),
+                    error: e.to_string(),
+                };
+                self.output.item(&failure, |failure| {
+                    format!("Failed {}: {}", failure.path.display(), failure.error)
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    fn is_candidate(&self, path: &Path) -> bool {
+        let hidden = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        !hidden
+            && path.parent() == Some(self.local_dir.as_path())
+            && path.is_file()
+            && is_supported(path)
+    }
+
+    async fn upload_file(
+        &mut self,
+        client: &mut RmClient,
+        path: &Path,
+        parent_id: &str,
+    ) -> Result<(), Error> {
+        let data = tokio::fs::read(path).await?;
+        let hash = client.compute_hash(&data);
+
+        let action = if self.state.uploaded.contains(&hash) {
+            log::info!("{} was uploaded before, skipping", path.display());
+            "skipped"
+        } else {
+            client.put_document(path, Some(parent_id)).await?;
+            self.state.uploaded.insert(hash);
+            self.state.save(&self.local_dir)?;
+            "uploaded"
+        };
+
+        let record = ChangeRecord::new(action, path).with_destination(&self.remote_dir);
+        self.output.item(&record, |record| {
+            let verb = if record.action == "uploaded" {
+                "Uploaded"
+            } else {
+                "Skipped already uploaded"
+            };
+            format!("{} {}", verb, record.path.display())
+        })?;
+
+        if self.move_done {
+            self.move_to_done(path)?;
+        }
+        Ok(())
+    }
+
+    fn move_to_done(&self, path: &Path) -> Result<(), Error> {
+        let done_dir = self.local_dir.join(DONE_DIR_NAME);
+        std::fs::create_dir_all(&done_dir)?;
+        let file_name = path.file_name().unwrap_or_default();
+        let mut target = done_dir.join(file_name);
+        if target.exists() {
+            // Keep earlier versions of a file that is dropped repeatedly
+            let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+            target = done_dir.join(format!("{}-{}", stamp, file_name.to_string_lossy()));
+        }
+        std::fs::rename(path, target)?;
+        Ok(())
+    }
+}